@@ -1,23 +1,22 @@
 #![allow(unused_imports)]
 use rodio::Source;
-use std::sync::{Arc, Mutex};
 use xmrs::prelude::*;
 use xmrs::xm::xmmodule::XmModule;
-use xmrsplayer::xmrsplayer::XmrsPlayer;
+use xmrsplayer::player_engine::PlayerEngine;
 
 pub const BUFFER_SIZE: usize = 2048;
 
 pub struct BufferedSource<'a> {
-    pub player: Arc<Mutex<XmrsPlayer<'a>>>,
+    pub engine: PlayerEngine<'a>,
     buffer: [f32; BUFFER_SIZE],
     buffer_index: usize,
     sample_rate: u32,
 }
 
 impl<'a> BufferedSource<'a> {
-    pub fn new(player: Arc<Mutex<XmrsPlayer<'a>>>, sample_rate: u32) -> Self {
+    pub fn new(engine: PlayerEngine<'a>, sample_rate: u32) -> Self {
         BufferedSource {
-            player,
+            engine,
             buffer: [0.0; BUFFER_SIZE],
             buffer_index: 0,
             sample_rate,
@@ -25,16 +24,7 @@ impl<'a> BufferedSource<'a> {
     }
 
     fn generate_samples(&mut self) {
-        let numsamples = self.buffer.len() / 2;
-        for i in 0..numsamples {
-            match self.player.lock().unwrap().sample() {
-                Some((left, right)) => {
-                    self.buffer[2 * i] = left;
-                    self.buffer[2 * i + 1] = right;
-                }
-                None => {}
-            }
-        }
+        self.engine.render_interleaved(&mut self.buffer);
     }
 }
 
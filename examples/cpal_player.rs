@@ -1,7 +1,6 @@
 use clap::Parser;
 use console::{Key, Term};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::sync::{Arc, Mutex};
 
 use xmrs::amiga::amiga_module::AmigaModule;
 use xmrs::prelude::*;
@@ -47,6 +46,10 @@ struct Cli {
     #[arg(short = 's', long, default_value = "0")]
     speed: u16,
 
+    /// Play a specific subsong (from 0 to n-1)
+    #[arg(short = 'u', long, default_value = "0")]
+    subsong: usize,
+
     /// Test SID player as a Proof of Concept
     #[cfg(feature = "sid")]
     #[arg(short = 'z', long, default_value = "false")]
@@ -70,19 +73,19 @@ fn sid_test_player(cli: &Cli) {
     // let sidmodule = SidModule::get_sid_sanxion_song_2();
     // let sidmodule = SidModule::get_sid_spellbound();
 
-    let modules = sidmodule.to_modules(false);
+    let mut modules = sidmodule.to_modules(false);
 
-    let leaked_modules: &'static [Module] = Box::leak(modules.into_boxed_slice());
-    let module_ref: &'static Module = &leaked_modules[0];
+    let module = modules.swap_remove(0);
 
     cpal_play(
-        module_ref,
+        module,
         cli.amplification,
         cli.position,
         cli.loops,
         cli.debug,
         cli.ch,
         cli.speed,
+        cli.subsong,
         false,
     );
 }
@@ -115,16 +118,15 @@ fn main() -> Result<(), std::io::Error> {
                             drop(xm);
                             println!("Playing {} !", module.name);
 
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             cpal_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
                                 cli.debug,
                                 cli.ch,
                                 cli.speed,
+                                cli.subsong,
                                 false,
                             );
                         }
@@ -140,16 +142,15 @@ fn main() -> Result<(), std::io::Error> {
                             let module = amiga.to_module();
                             drop(amiga);
                             println!("Playing {} !", module.name);
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             cpal_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
                                 cli.debug,
                                 cli.ch,
                                 cli.speed,
+                                cli.subsong,
                                 false,
                             );
                         }
@@ -165,16 +166,15 @@ fn main() -> Result<(), std::io::Error> {
                             let module = s3m.to_module();
                             drop(s3m);
                             println!("Playing {} !", module.name);
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             cpal_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
                                 cli.debug,
                                 cli.ch,
                                 cli.speed,
+                                cli.subsong,
                                 false,
                             );
                         }
@@ -194,13 +194,14 @@ fn main() -> Result<(), std::io::Error> {
 }
 
 fn cpal_play(
-    module: &'static Module,
+    module: Module,
     amplification: f32,
     position: usize,
     loops: usize,
     debug: bool,
     ch: u8,
     speed: u16,
+    subsong: usize,
     historical: bool,
 ) {
     let host = cpal::default_host();
@@ -218,39 +219,56 @@ fn cpal_play(
         || module.comment == "FastTracker v2.00 (1.03)"
         || module.comment == "FastTracker v2.00 (1.04)";
 
-    let player = Arc::new(Mutex::new(XmrsPlayer::new(
-        module,
-        sample_rate.0 as f32,
-        is_ft2,
-    )));
+    let song_length = module.pattern_order.len();
+    let mut player = XmrsPlayer::from_owned(module, sample_rate.0 as f32, is_ft2);
+    let subsongs = player.subsongs();
+    if subsongs.len() > 1 {
+        for (i, s) in subsongs.iter().enumerate() {
+            let duration = s.duration().as_secs();
+            println!(
+                "Subsong {}: position {:02x}, {:02}:{:02}",
+                i,
+                s.table_index,
+                duration / 60,
+                duration % 60
+            );
+        }
+    }
+    let start = subsongs.get(subsong).map_or(0, |s| s.table_index);
 
-    {
-        let mut player_lock = player.lock().unwrap();
-        player_lock.amplification = amplification;
-        if debug {
-            println!("Debug on");
-            if is_ft2 {
-                println!("FT2 Historical XM detected.")
-            }
+    let timeline =
+        SongTimeline::new_from(player.module(), sample_rate.0 as f32, is_ft2, loops, start);
+    let duration = timeline.duration().as_secs();
+    println!("Duration: {:02}:{:02}", duration / 60, duration % 60);
+
+    player.amplification = amplification;
+    if debug {
+        println!("Debug on");
+        if is_ft2 {
+            println!("FT2 Historical XM detected.")
         }
-        player_lock.debug(debug);
-        if ch != 0 {
-            player_lock.mute_all(true);
-            player_lock.set_mute_channel((ch - 1).into(), false);
+    }
+    player.debug(debug);
+    // modules loaded by xmrs importers play their original tracker effects
+    player.set_compatibility(Compatibility::guess(player.module()));
+    if ch != 0 {
+        player.solo_channel((ch - 1).into(), true);
+    }
+    player.set_max_loop_count(loops);
+    if subsong != 0 {
+        if !player.select_subsong(subsong) {
+            println!("Subsong {} not found", subsong);
         }
-        player_lock.set_max_loop_count(loops);
-        player_lock.goto(position, 0, speed);
+    } else {
+        player.goto(position, 0, speed);
     }
 
-    let player_clone = Arc::clone(&player);
+    let (mut engine, controller) = player.split(64);
     let stream = device
         .build_output_stream(
             &config.config(),
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut player_lock = player_clone.lock().unwrap();
-                for sample in data.iter_mut() {
-                    *sample = player_lock.next().unwrap_or(0.0);
-                }
+                engine.render_interleaved(data);
             },
             |_: cpal::StreamError| {},
             None,
@@ -268,42 +286,45 @@ fn cpal_play(
         if let Ok(character) = stdout.read_key() {
             match character {
                 Key::Enter => {
-                    let ti = player.lock().unwrap().get_current_table_index();
-                    let p = player.lock().unwrap().get_current_pattern();
-                    println!("current table index:{:02x}, current pattern:{:02x}", ti, p);
+                    let status = controller.status();
+                    println!(
+                        "current table index:{:02x}, current pattern:{:02x}",
+                        status.table_index, status.pattern
+                    );
+                    let frames = status.generated_samples;
+                    let total = timeline.total_frames().max(1);
+                    let width = 40 * frames.min(total) as usize / total as usize;
+                    println!("[{:<40}] {}%", "#".repeat(width), 100 * frames / total);
                 }
                 Key::Escape => {
                     println!("Have a nice day!");
                     return;
                 }
                 Key::ArrowLeft => {
-                    let i = player.lock().unwrap().get_current_table_index();
+                    let i = controller.status().table_index;
                     if i != 0 {
-                        player.lock().unwrap().goto(i - 1, 0, 0);
+                        controller.goto(i - 1, 0, 0);
                     }
                 }
                 Key::ArrowRight => {
-                    let len = module.pattern_order.len();
-                    let i = player.lock().unwrap().get_current_table_index();
-                    if i + 1 < len {
-                        player.lock().unwrap().goto(i + 1, 0, 0);
+                    let i = controller.status().table_index;
+                    if i + 1 < song_length {
+                        controller.goto(i + 1, 0, 0);
                     }
                 }
                 Key::Char(' ') => {
                     if playing {
                         println!("Pause, press space to continue");
-                        player.lock().unwrap().pause(true);
+                        controller.pause(true);
                         playing = false;
-                        {
-                            let player_lock = player.lock().unwrap();
-                            let ti = player_lock.get_current_table_index();
-                            let p = player_lock.get_current_pattern();
-                            let row = player_lock.get_current_row();
-                            println!("Pattern [{:02X}]={:02X}, Row {:02X}", ti, p, row);
-                        }
+                        let status = controller.status();
+                        println!(
+                            "Pattern [{:02X}]={:02X}, Row {:02X}",
+                            status.table_index, status.pattern, status.row
+                        );
                     } else {
                         println!("Playing");
-                        player.lock().unwrap().pause(false);
+                        controller.pause(false);
                         playing = true;
                     }
                 }
@@ -1,7 +1,6 @@
 use clap::Parser;
 use console::{Key, Term};
 use rodio::Sink;
-use std::sync::{Arc, Mutex};
 
 use xmrs::amiga::amiga_module::AmigaModule;
 use xmrs::prelude::*;
@@ -71,10 +70,8 @@ fn main() -> Result<(), std::io::Error> {
                             drop(xm);
                             println!("Playing {} !", module.name);
 
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             rodio_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
@@ -96,10 +93,8 @@ fn main() -> Result<(), std::io::Error> {
                             let module = amiga.to_module();
                             drop(amiga);
                             println!("Playing {} !", module.name);
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             rodio_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
@@ -121,10 +116,8 @@ fn main() -> Result<(), std::io::Error> {
                             let module = s3m.to_module();
                             drop(s3m);
                             println!("Playing {} !", module.name);
-                            let module = Box::new(module);
-                            let module_ref: &'static Module = Box::leak(module);
                             rodio_play(
-                                module_ref,
+                                module,
                                 cli.amplification,
                                 cli.position,
                                 cli.loops,
@@ -150,7 +143,7 @@ fn main() -> Result<(), std::io::Error> {
 }
 
 fn rodio_play(
-    module: &'static Module,
+    module: Module,
     amplification: f32,
     position: usize,
     loops: usize,
@@ -168,29 +161,28 @@ fn rodio_play(
         || module.comment == "FastTracker v2.00 (1.03)"
         || module.comment == "FastTracker v2.00 (1.04)";
 
-    let player = Arc::new(Mutex::new(XmrsPlayer::new(
-        module,
-        SAMPLE_RATE as f32,
-        is_ft2,
-    )));
-    {
-        let mut player_lock = player.lock().unwrap();
-        player_lock.amplification = amplification;
-        if debug {
-            println!("Debug on");
-            if is_ft2 {
-                println!("FT2 Historical XM detected.")
-            }
-        }
-        player_lock.debug(debug);
-        if ch != 0 {
-            player_lock.mute_all(true);
-            player_lock.set_mute_channel((ch - 1).into(), false);
+    let timeline = SongTimeline::new(&module, SAMPLE_RATE as f32, is_ft2, loops);
+    let duration = timeline.duration().as_secs();
+    println!("Duration: {:02}:{:02}", duration / 60, duration % 60);
+
+    let song_length = module.pattern_order.len();
+    let mut player = XmrsPlayer::from_owned(module, SAMPLE_RATE as f32, is_ft2);
+    player.amplification = amplification;
+    if debug {
+        println!("Debug on");
+        if is_ft2 {
+            println!("FT2 Historical XM detected.")
         }
-        player_lock.set_max_loop_count(loops);
-        player_lock.goto(position, 0, speed);
     }
-    let source = BufferedSource::new(Arc::clone(&player), SAMPLE_RATE);
+    player.debug(debug);
+    if ch != 0 {
+        player.solo_channel((ch - 1).into(), true);
+    }
+    player.set_max_loop_count(loops);
+    player.goto(position, 0, speed);
+
+    let (engine, controller) = player.split(64);
+    let source = BufferedSource::new(engine, SAMPLE_RATE);
     sink.append(source);
     // sink.append(player.buffered());
     sink.play();
@@ -204,9 +196,15 @@ fn rodio_play(
         if let Ok(character) = stdout.read_key() {
             match character {
                 Key::Enter => {
-                    let ti = player.lock().unwrap().get_current_table_index();
-                    let p = player.lock().unwrap().get_current_pattern();
-                    println!("current table index:{:02x}, current pattern:{:02x}", ti, p);
+                    let status = controller.status();
+                    println!(
+                        "current table index:{:02x}, current pattern:{:02x}",
+                        status.table_index, status.pattern
+                    );
+                    let frames = status.generated_samples;
+                    let total = timeline.total_frames().max(1);
+                    let width = 40 * frames.min(total) as usize / total as usize;
+                    println!("[{:<40}] {}%", "#".repeat(width), 100 * frames / total);
                 }
                 Key::Escape => {
                     println!("Have a nice day!");
@@ -214,16 +212,15 @@ fn rodio_play(
                     return;
                 }
                 Key::ArrowLeft => {
-                    let i = player.lock().unwrap().get_current_table_index();
+                    let i = controller.status().table_index;
                     if i != 0 {
-                        player.lock().unwrap().goto(i - 1, 0, 0);
+                        controller.goto(i - 1, 0, 0);
                     }
                 }
                 Key::ArrowRight => {
-                    let len = module.pattern_order.len();
-                    let i = player.lock().unwrap().get_current_table_index();
-                    if i + 1 < len {
-                        player.lock().unwrap().goto(i + 1, 0, 0);
+                    let i = controller.status().table_index;
+                    if i + 1 < song_length {
+                        controller.goto(i + 1, 0, 0);
                     }
                 }
                 Key::Char(' ') => {
@@ -231,13 +228,11 @@ fn rodio_play(
                         println!("Pause, press space to continue");
                         sink.pause();
                         playing = false;
-                        {
-                            let player_lock = player.lock().unwrap();
-                            let ti = player_lock.get_current_table_index();
-                            let p = player_lock.get_current_pattern();
-                            let row = player_lock.get_current_row();
-                            println!("Pattern [{:02X}]={:02X}, Row {:02X}", ti, p, row);
-                        }
+                        let status = controller.status();
+                        println!(
+                            "Pattern [{:02X}]={:02X}, Row {:02X}",
+                            status.table_index, status.pattern, status.row
+                        );
                     } else {
                         println!("Playing");
                         sink.play();
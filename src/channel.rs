@@ -3,24 +3,38 @@ use micromath::F32Ext;
 #[cfg(feature = "libm")]
 use num_traits::float::Float;
 
+use crate::channel_info::{ActiveEffects, ChannelInfo, LoopDirection};
+use crate::compatibility::Compatibility;
 use crate::effect::*;
 use crate::effect_arpeggio::EffectArpeggio;
+use crate::effect_invert_loop::EffectInvertLoop;
 use crate::effect_multi_retrig_note::EffectMultiRetrigNote;
 use crate::effect_portamento::EffectPortamento;
 use crate::effect_toneportamento::EffectTonePortamento;
+use crate::effect_s3m_memory::EffectS3mMemory;
+use crate::effect_tremor::EffectTremor;
 use crate::effect_vibrato_tremolo::EffectVibratoTremolo;
 use crate::effect_volume_panning_slide::EffectVolumePanningSlide;
 use crate::historical_helper::HistoricalHelper;
+use crate::interpolation::Interpolator;
+use crate::rng::Rng;
 use crate::triggerkeep::*;
 
 use crate::helper::*;
-use crate::state_instr_default::StateInstrDefault;
+use crate::state_envelope::StateEnvelope;
+use crate::state_instr_default::{instr_default, StateInstrDefault};
+use crate::state_sample::StateSample;
+use crate::state_volume_ramp::StateVolumeRamp;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use xmrs::prelude::*;
 
 #[derive(Clone)]
-pub struct Channel<'a> {
-    module: &'a Module,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Channel {
     historical: Option<HistoricalHelper>,
+    /// Not serialized, see `reset_period_helper()`
+    #[cfg_attr(feature = "serde", serde(skip))]
     period_helper: PeriodHelper,
     rate: f32,
 
@@ -34,7 +48,7 @@ pub struct Channel<'a> {
     panning: f32, /* Between 0 (left) and 1 (right); 0.5 is centered */
 
     // Instrument
-    instr: Option<StateInstrDefault<'a>>,
+    instr: Option<StateInstrDefault>,
 
     arpeggio: EffectArpeggio,
     multi_retrig_note: EffectMultiRetrigNote,
@@ -53,6 +67,8 @@ pub struct Channel<'a> {
     volume_slide: EffectVolumePanningSlide,
     volume_slide_tick0: EffectVolumePanningSlide,
     vibrato: EffectVibratoTremolo,
+    panbrello: EffectVibratoTremolo,
+    invert_loop: EffectInvertLoop,
 
     semitone: bool,
 
@@ -62,19 +78,50 @@ pub struct Channel<'a> {
     /// How many loop passes have been done
     pub(crate) pattern_loop_count: usize,
 
-    tremor_param: u8,
-    tremor_on: bool,
+    tremor: EffectTremor,
+    s3m_memory: EffectS3mMemory,
 
     pub muted: bool,
 
+    /// (left, right) volume, panning 0.0 is left
     actual_volume: [f32; 2],
+    /// Volume really applied, following actual_volume
+    ramp: StateVolumeRamp,
+    /// Frames to go from a volume to another, 0 to disable ramping
+    #[cfg_attr(feature = "serde", serde(skip))]
+    volume_ramp: usize,
+    /// Fade out the previous voice when a note starts
+    #[cfg_attr(feature = "serde", serde(skip))]
+    note_fadeout: bool,
+    /// Previous voice, fading out
+    fadeout: Option<(StateSample, StateVolumeRamp)>,
+    /// Output side (0 is left, 1 is right) if the channel is hard panned
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hard_pan: Option<usize>,
+    /// 0.0 is mono, 1.0 is as authored, 2.0 is wider
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stereo_separation: f32,
+    /// Mixer volume, 0.0 if another channel is solo
+    #[cfg_attr(feature = "serde", serde(skip))]
+    mixer_volume: f32,
+    /// Mixer panning, replacing the song panning
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pan_override: Option<f32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    compatibility: Compatibility,
+
+    /// Last note triggered
+    last_note: Note,
+    /// A note was triggered since the last `take_note_on()`
+    note_on: bool,
+    /// A note was released since the last `take_note_off()`
+    note_off: bool,
 }
 
-impl<'a> Channel<'a> {
-    pub(crate) fn new(module: &'a Module, rate: f32, historical: Option<HistoricalHelper>) -> Self {
+impl Channel {
+    pub(crate) fn new(module: &Module, rate: f32, historical: Option<HistoricalHelper>) -> Self {
         let period_helper = PeriodHelper::new(module.frequency_type, historical.is_some());
         Self {
-            module,
             historical: historical.clone(),
             period_helper: period_helper.clone(),
             rate,
@@ -82,8 +129,10 @@ impl<'a> Channel<'a> {
             panning: 0.5,
             arpeggio: EffectArpeggio::new(historical.clone()),
             tone_portamento: EffectTonePortamento::new(period_helper.clone()),
-            vibrato: EffectVibratoTremolo::vibrato(&period_helper),
-            tremolo: EffectVibratoTremolo::tremolo(),
+            vibrato: EffectVibratoTremolo::vibrato(&period_helper, historical.is_some()),
+            tremolo: EffectVibratoTremolo::tremolo(historical.is_some()),
+            panbrello: EffectVibratoTremolo::panbrello(),
+            invert_loop: EffectInvertLoop::new(module),
             multi_retrig_note: EffectMultiRetrigNote::new(historical, 0.0, 0.0),
             note: 0.0,
             current: PatternSlot::default(),
@@ -102,30 +151,283 @@ impl<'a> Channel<'a> {
             note_delay_param: 0,
             pattern_loop_origin: 0,
             pattern_loop_count: 0,
-            tremor_param: 0,
-            tremor_on: false,
+            tremor: EffectTremor::new(),
+            s3m_memory: EffectS3mMemory::default(),
             muted: false,
             actual_volume: [0.0, 0.0],
+            ramp: StateVolumeRamp::default(),
+            volume_ramp: 0,
+            note_fadeout: false,
+            fadeout: None,
+            hard_pan: None,
+            stereo_separation: 1.0,
+            mixer_volume: 1.0,
+            pan_override: None,
+            compatibility: Compatibility::Xm,
+            last_note: Note::None,
+            note_on: false,
+            note_off: false,
         }
     }
 
-    pub fn is_muted(&self) -> bool {
-        let midi_mute = if let Some(i) = &self.instr {
-            i.midi_mute_computer
-        } else {
-            false
+    /// Rebuild period helpers, which are not serialized
+    pub(crate) fn reset_period_helper(&mut self, module: &Module) {
+        self.period_helper = PeriodHelper::new(module.frequency_type, self.historical.is_some());
+        self.tone_portamento
+            .set_period_helper(self.period_helper.clone());
+    }
+
+    /// Seed the random waveforms of vibrato, tremolo and panbrello
+    pub(crate) fn set_random_seed(&mut self, seed: u32) {
+        self.vibrato.set_random_seed(Rng::derive(seed, 0));
+        self.tremolo.set_random_seed(Rng::derive(seed, 1));
+        self.panbrello.set_random_seed(Rng::derive(seed, 2));
+    }
+
+    /// Returns true if every instrument or sample index still points to `module` data
+    pub(crate) fn is_valid_for(&self, module: &Module) -> bool {
+        self.instr.as_ref().is_none_or(|i| i.is_valid_for(module))
+            && self.fadeout.as_ref().is_none_or(|(s, _)| {
+                s.get_sample(module)
+                    .is_some_and(|sample| s.is_valid_for(sample))
+            })
+            && self.invert_loop.is_valid()
+    }
+
+    /// Ramp volume changes over `frames` frames (0 disables it), and fade out the previous voice
+    /// when a note starts if `note_fadeout` is true
+    pub(crate) fn set_volume_ramp(&mut self, frames: usize, note_fadeout: bool) {
+        self.volume_ramp = frames;
+        self.note_fadeout = note_fadeout;
+        if frames == 0 {
+            self.ramp.set_target(self.actual_volume, 0);
+        }
+        if frames == 0 || !note_fadeout {
+            self.fadeout = None;
+        }
+    }
+
+    pub fn is_muted(&self, module: &Module) -> bool {
+        let midi_mute = match &self.instr {
+            Some(i) => i.get_instr(module).is_some_and(|id| id.midi_mute_computer),
+            None => false,
         };
         self.muted || midi_mute
     }
 
+    /// Returns the sample played by this channel
+    pub(crate) fn sample_ref<'m>(&self, module: &'m Module) -> Option<&'m Sample> {
+        self.instr
+            .as_ref()?
+            .state_sample
+            .as_ref()?
+            .get_sample(module)
+    }
+
+    /// Send the channel to one side only (0 is left, 1 is right), panning is then ignored
+    pub(crate) fn set_hard_pan(&mut self, side: Option<usize>) {
+        self.hard_pan = side;
+    }
+
+    pub(crate) fn set_stereo_separation(&mut self, separation: f32) {
+        self.stereo_separation = separation;
+    }
+
+    /// Mixer settings: `volume` is applied after every song volume, `pan_override` replaces the
+    /// song panning (0.0 is left, 1.0 is right)
+    pub(crate) fn set_mixer(&mut self, volume: f32, pan_override: Option<f32>) {
+        self.mixer_volume = volume;
+        self.pan_override = pan_override;
+    }
+
+    pub(crate) fn set_compatibility(&mut self, compatibility: Compatibility) {
+        self.compatibility = compatibility;
+        let s3m = matches!(compatibility, Compatibility::ScreamTracker { .. });
+        if self.tremor.is_s3m() != s3m {
+            self.tremor = if s3m {
+                EffectTremor::s3m()
+            } else {
+                EffectTremor::new()
+            };
+        }
+    }
+
+    /// Scream Tracker 3.00 slides the volume on the first tick of the row too
+    fn tick0_fast_volume_slide(&mut self) {
+        if let Compatibility::ScreamTracker {
+            fast_volume_slides: true,
+        } = self.compatibility
+        {
+            self.volume += self.volume_slide.tick();
+        }
+    }
+
+    /// ProTracker stops portamentos at 113 going up and at 856 going down
+    fn protracker_period_limit(&mut self, up: bool) {
+        if self.compatibility == Compatibility::ProTracker
+            && matches!(
+                self.period_helper.freq_type,
+                FrequencyType::AmigaFrequencies
+            )
+        {
+            self.period = if up {
+                self.period.max(113.0)
+            } else {
+                self.period.min(856.0)
+            };
+        }
+    }
+
+    /// Returns the sample of the voice fading out
+    pub(crate) fn fadeout_sample_ref<'m>(&self, module: &'m Module) -> Option<&'m Sample> {
+        self.fadeout.as_ref()?.0.get_sample(module)
+    }
+
+    /// Same as calling `next_frame()` and `next_fadeout_frame()` `frames` times, without computing any sample
+    pub(crate) fn skip(&mut self, frames: usize) {
+        // Like `next_frame()`, ramps only move while their sample plays
+        if let Some(i) = &mut self.instr {
+            let played = i.skip(frames);
+            self.ramp.skip(played);
+        }
+        if let Some((s, ramp)) = &mut self.fadeout {
+            let played = s.skip(frames);
+            ramp.skip(played);
+            if ramp.is_done() || !s.is_enabled() {
+                self.fadeout = None;
+            }
+        }
+    }
+
+    /// Keep the voice playing, to fade it out while a new note starts
+    fn fade_out_voice(&mut self) {
+        if self.volume_ramp == 0 || !self.note_fadeout {
+            return;
+        }
+        if let Some(s) = self.instr.as_ref().and_then(|i| i.state_sample.as_ref()) {
+            if s.is_enabled() && s.is_started() {
+                let mut ramp = StateVolumeRamp::new(self.ramp.volume());
+                ramp.set_target([0.0, 0.0], self.volume_ramp);
+                self.fadeout = Some((s.clone(), ramp));
+            }
+        }
+    }
+
+    /// Returns (instrument, note, volume) if a note was triggered since the last call
+    pub(crate) fn take_note_on(&mut self) -> Option<(usize, Note, f32)> {
+        if !core::mem::take(&mut self.note_on) {
+            return None;
+        }
+        self.instr
+            .as_ref()
+            .map(|i| (i.num, self.last_note, self.volume))
+    }
+
+    /// Returns the last note triggered, if its voice is still playing
+    pub(crate) fn playing_note(&self) -> Option<Note> {
+        match &self.instr {
+            Some(i) if i.is_enabled() => Some(self.last_note),
+            _ => None,
+        }
+    }
+
+    /// Returns a snapshot of the channel state
+    pub(crate) fn info(&self, module: &Module) -> ChannelInfo {
+        let mut info = ChannelInfo {
+            note: self.playing_note(),
+            effect: (self.current.effect_type, self.current.effect_parameter),
+            volume_column: self.current.volume,
+            effects: ActiveEffects::new(&self.current),
+            muted: self.is_muted(module),
+            fadeout: 1.0,
+            ..Default::default()
+        };
+        let instr = match &self.instr {
+            Some(i) => i,
+            None => return info,
+        };
+        info.instrument = Some(instr.num);
+        info.fadeout = instr.volume_fadeout;
+        info.released = !instr.sustained;
+        let envelope_position = |e: &StateEnvelope| e.has_volume_envelope().then_some(e.counter);
+        info.volume_envelope = envelope_position(&instr.envelope_volume);
+        info.panning_envelope = envelope_position(&instr.envelope_panning);
+        if let Some((volume, panning)) = self.output_volume_panning() {
+            info.volume = volume;
+            info.panning = match (self.pan_override, self.hard_pan) {
+                (Some(panning), _) => panning,
+                (None, Some(side)) => side as f32,
+                (None, None) => panning,
+            };
+        }
+        if let Some(s) = instr.state_sample.as_ref() {
+            info.sample = Some(s.get_sample_num());
+            if let Some(position) = s.get_position() {
+                info.position = Some(position);
+                info.direction = if s.is_backward() {
+                    LoopDirection::Backward
+                } else {
+                    LoopDirection::Forward
+                };
+                info.frequency = s.get_frequency();
+                if info.frequency > 0.0 {
+                    // Same sum as `PeriodHelper::adjust_period()`
+                    let (period, vibrato) = self.output_period();
+                    let arp_note = if self.current.has_arpeggio() {
+                        self.arpeggio.value()
+                    } else {
+                        0.0
+                    };
+                    info.pitch = self.period_helper.period_to_note(period)
+                        + arp_note
+                        + vibrato
+                        + instr.state_vibrato.current_modulation
+                        - s.get_finetuned_note();
+                }
+            }
+        }
+        if info.position.is_none() {
+            info.volume = 0.0;
+        }
+        info
+    }
+
+    /// Stop every voice immediately
+    pub(crate) fn stop(&mut self) {
+        self.instr = None;
+        self.fadeout = None;
+        self.actual_volume = [0.0, 0.0];
+        self.ramp = StateVolumeRamp::default();
+    }
+
+    /// True while the previous voice fades out
+    pub(crate) fn is_fading_out(&self) -> bool {
+        self.fadeout.is_some()
+    }
+
+    /// True if a voice plays, or will play again: a released note faded out to silence is over,
+    /// even if its looped sample goes on
+    pub(crate) fn is_busy(&self) -> bool {
+        let faded_out = self
+            .instr
+            .as_ref()
+            .is_some_and(|i| !i.sustained && i.volume_fadeout == 0.0);
+        (self.playing_note().is_some() && !faded_out) || self.is_fading_out()
+    }
+
+    /// Returns true if a note was released since the last call
+    pub(crate) fn take_note_off(&mut self) -> bool {
+        core::mem::take(&mut self.note_off)
+    }
+
     fn cut_note(&mut self) {
         /* NB: this is not the same as Key Off */
         self.volume = 0.0;
     }
 
-    fn key_off_historical(&mut self, tick: u16) {
+    fn key_off_historical(&mut self, module: &Module, tick: u16) {
         if let Some(i) = &mut self.instr {
-            i.key_off();
+            i.key_off(module);
             // openmpt `key_off.xm`: Key off at tick 0 (K00) is very dodgy command. If there is a note next to it, the note is ignored. If there is a volume column command or instrument next to it and the current instrument has no volume envelope, the note is faded out instead of being cut.
             if (tick == 0
                 && (i.has_volume_envelope()
@@ -144,21 +446,39 @@ impl<'a> Channel<'a> {
         }
     }
 
-    fn key_off(&mut self, tick: u16) {
+    fn key_off(&mut self, module: &Module, tick: u16) {
+        self.note_off = true;
+
         if let Some(_hhelper) = &self.historical {
-            self.key_off_historical(tick);
+            self.key_off_historical(module, tick);
             return;
         }
 
         if let Some(i) = &mut self.instr {
-            i.key_off();
+            i.key_off(module);
         } else {
             self.cut_note();
         }
     }
 
+    /// Trigger the last note again (E9x, Rxy...), reported like a new note
+    fn retrigger_note(&mut self, flags: TriggerKeep) {
+        self.trigger_note(flags);
+        self.note_on = true;
+    }
+
     pub(crate) fn trigger_note(&mut self, flags: TriggerKeep) {
-        self.tremor_on = false;
+        self.tremor.retrigger();
+
+        if !contains(flags, TRIGGER_KEEP_SAMPLE_POSITION) {
+            self.fade_out_voice();
+            if self.volume_ramp > 0 {
+                self.ramp.silence();
+            }
+            self.vibrato.retrigger();
+            self.tremolo.retrigger();
+            self.panbrello.retrigger();
+        }
 
         match &mut self.instr {
             Some(instr) => {
@@ -182,30 +502,87 @@ impl<'a> Channel<'a> {
 
                 if !contains(flags, TRIGGER_KEEP_PERIOD) {
                     self.period = self.period_helper.note_to_period(self.note);
-                    instr.update_frequency(self.period, 0.0, self.vibrato.value(), self.semitone);
+                    instr.update_frequency(
+                        &self.period_helper,
+                        self.period,
+                        0.0,
+                        self.vibrato.value(),
+                    );
+                }
+
+                /* EFx: Invert loop, sized here and not while mixing */
+                if self.compatibility == Compatibility::ProTracker {
+                    if let Some(state) = &instr.state_sample {
+                        self.invert_loop.select(state);
+                    }
                 }
             }
             None => {}
         }
     }
 
+    /// Returns (volume, panning) after envelopes, tremolo, tremor and mixer volume, before
+    /// hard panning and panning override
+    fn output_volume_panning(&self) -> Option<(f32, f32)> {
+        let instr = self.instr.as_ref()?;
+        let base = (self.panning + self.panbrello.value()).clamp(0.0, 1.0);
+        let panning: f32 =
+            base + (instr.envelope_panning.value - 0.5) * (0.5 - (base - 0.5).abs()) * 2.0;
+        let mut volume = 0.0;
+
+        if self.tremor.value() != 0.0 {
+            volume = self.volume + self.tremolo.value();
+            clamp(&mut volume);
+            volume *= instr.get_volume();
+        }
+        volume *= self.mixer_volume;
+        Some((volume, panning))
+    }
+
+    /// Returns (period, vibrato) to play. During a tone portamento with glissando (E3y), the period
+    /// is snapped to the semitones of the sample finetune.
+    fn output_period(&self) -> (f32, f32) {
+        let mut vibrato = self.vibrato.value();
+        if !self.semitone || !self.current.has_tone_portamento() || self.period == 0.0 {
+            return (self.period, vibrato);
+        }
+        if self.historical.is_some() {
+            // FT2: the effect column runs after the volume column, and wins
+            match (self.current.effect_type, self.current.volume >> 4) {
+                (3 | 5, 0xB) => vibrato = 0.0,
+                (4 | 6, 0xF) => return (self.period, vibrato),
+                _ => {}
+            }
+        }
+        let finetune = self.instr.as_ref().map_or(0.0, |i| i.get_finetune());
+        let note = self.period_helper.period_to_note(self.period) - finetune;
+        let note = if self.historical.is_some() {
+            // Period tables: the first period not above the current one
+            (note - 0.001).ceil()
+        } else {
+            note.round()
+        };
+        (self.period_helper.note_to_period(note + finetune), vibrato)
+    }
+
     fn tickn_update_instr(&mut self) {
+        let (volume, panning) = match self.output_volume_panning() {
+            Some(vp) => vp,
+            None => return,
+        };
+        let (period, vibrato) = self.output_period();
         match &mut self.instr {
             Some(instr) => {
-                let panning: f32 = self.panning
-                    + (instr.envelope_panning.value - 0.5)
-                        * (0.5 - (self.panning - 0.5).abs())
-                        * 2.0;
-                let mut volume = 0.0;
-
-                if !self.tremor_on {
-                    volume = self.volume + self.tremolo.value();
-                    clamp(&mut volume);
-                    volume *= instr.get_volume();
-                }
-
-                self.actual_volume[0] = volume * panning.sqrt();
-                self.actual_volume[1] = volume * (1.0 - panning).sqrt();
+                let volume = match self.hard_pan {
+                    Some(side) if self.pan_override.is_none() => {
+                        let mut volumes = [0.0, 0.0];
+                        volumes[side] = volume;
+                        volumes
+                    }
+                    _ => pan_law(volume, self.pan_override.unwrap_or(panning)),
+                };
+                self.actual_volume = stereo_separation(volume, self.stereo_separation);
+                self.ramp.set_target(self.actual_volume, self.volume_ramp);
 
                 let arp_note = if self.current.has_arpeggio() {
                     self.arpeggio.value()
@@ -213,13 +590,13 @@ impl<'a> Channel<'a> {
                     0.0
                 };
 
-                instr.update_frequency(self.period, arp_note, self.vibrato.value(), self.semitone)
+                instr.update_frequency(&self.period_helper, period, arp_note, vibrato)
             }
             None => {}
         }
     }
 
-    fn tick_effects(&mut self, current_tick: u16) {
+    fn tick_effects(&mut self, module: &Module, current_tick: u16) {
         match self.current.effect_type {
             0 => {
                 /* 0xy: Arpeggio */
@@ -231,11 +608,13 @@ impl<'a> Channel<'a> {
                 /* 1xx: Portamento up */
                 self.portamento_up.tick();
                 self.period = self.portamento_up.clamp(self.period);
+                self.protracker_period_limit(true);
             }
             2 if current_tick != 0 => {
                 /* 2xx: Portamento down */
                 self.portamento_down.tick();
                 self.period = self.portamento_down.clamp(self.period);
+                self.protracker_period_limit(false);
             }
             3 if current_tick != 0 => {
                 /* 3xx: Tone portamento */
@@ -263,6 +642,10 @@ impl<'a> Channel<'a> {
                 /* 7xy: Tremolo */
                 self.tremolo.tick();
             }
+            0x22 if current_tick != 0 => {
+                /* Yxy: Panbrello */
+                self.panbrello.tick();
+            }
             0xA if current_tick != 0 => {
                 /* Axy: Volume slide */
                 self.volume += self.volume_slide.tick();
@@ -275,9 +658,9 @@ impl<'a> Channel<'a> {
                         if self.current.effect_parameter & 0x0F != 0 {
                             let r = current_tick % (self.current.effect_parameter as u16 & 0x0F);
                             if r == 0 {
-                                self.trigger_note(TRIGGER_KEEP_VOLUME);
+                                self.retrigger_note(TRIGGER_KEEP_VOLUME);
                                 if let Some(instr) = &mut self.instr {
-                                    instr.tick();
+                                    instr.tick(module);
                                 }
                             }
                         }
@@ -286,16 +669,17 @@ impl<'a> Channel<'a> {
                         /* ECy: Note cut */
                         if (self.current.effect_parameter as u16 & 0x0F) == current_tick {
                             self.cut_note();
+                            self.note_off = true;
                         }
                     }
                     0xD => {
                         /* EDy: Note delay */
                         if self.note_delay_param as u16 == current_tick {
-                            self.tick0_load_instrument_and_note();
+                            self.tick0_load_instrument_and_note(module);
                             // Volume effect
                             self.tick0_volume_effects();
                             // Effects
-                            self.tick0_effects();
+                            self.tick0_effects(module);
 
                             /* Special KeyOff cases */
                             if self.current.note.is_keyoff() {
@@ -304,7 +688,7 @@ impl<'a> Channel<'a> {
                                         i.volume_reset();
                                     }
                                 } else {
-                                    self.trigger_note(TRIGGER_KEEP_NONE);
+                                    self.retrigger_note(TRIGGER_KEEP_NONE);
                                 }
                             }
                         }
@@ -315,7 +699,7 @@ impl<'a> Channel<'a> {
             0x14 => {
                 /* Kxx: Key off */
                 if current_tick == self.current.effect_parameter as u16 {
-                    self.key_off(current_tick);
+                    self.key_off(module, current_tick);
                 }
             }
             0x19 if current_tick != 0 => {
@@ -326,9 +710,15 @@ impl<'a> Channel<'a> {
             0x1B if current_tick != 0 => {
                 /* Rxy: Multi retrig note */
                 if self.multi_retrig_note.tick() == 0.0 {
-                    self.trigger_note(TRIGGER_KEEP_VOLUME | TRIGGER_KEEP_ENVELOPE);
-                    if let Some(instr) = &self.instr {
-                        if self.volume == 0.0 && !instr.volume_envelope.enabled {
+                    self.retrigger_note(TRIGGER_KEEP_VOLUME | TRIGGER_KEEP_ENVELOPE);
+                    if matches!(self.compatibility, Compatibility::ScreamTracker { .. }) {
+                        // S3M Qxy changes the volume at each retrigger
+                        self.volume = self.multi_retrig_note.s3m_volume(self.volume);
+                    } else if let Some(instr) = &self.instr {
+                        let volume_envelope = instr
+                            .get_instr(module)
+                            .is_some_and(|id| id.volume_envelope.enabled);
+                        if self.volume == 0.0 && !volume_envelope {
                             self.volume = self.multi_retrig_note.clamp(self.volume);
                             let current_volume = self.current.volume;
                             if (0x10..=0x50).contains(&current_volume) {
@@ -343,15 +733,8 @@ impl<'a> Channel<'a> {
                 }
             }
             0x1D if current_tick != 0 => {
-                /* Txy: Tremor
-                    Rapidly switches the sample volume on and off on every tick of the row except the first.
-                    Volume is on for x + 1 ticks and off for y + 1 ticks.
-
-                    tremor_on: bool = [(T-1) % (X+1+Y+1) ] > X
-                */
-                self.tremor_on = (current_tick - 1)
-                    % ((self.tremor_param as u16 >> 4) + (self.tremor_param as u16 & 0x0F) + 2)
-                    > (self.tremor_param as u16 >> 4);
+                /* Txy: Tremor */
+                self.tremor.tick();
             }
             _ => {}
         }
@@ -384,20 +767,25 @@ impl<'a> Channel<'a> {
         }
     }
 
-    pub(crate) fn tick(&mut self, current_tick: u16) {
+    pub(crate) fn tick(&mut self, module: &Module, current_tick: u16) {
+        if current_tick != 0 && self.compatibility == Compatibility::ProTracker {
+            /* EFx: Invert loop, until EF0 */
+            let state = self.instr.as_ref().and_then(|i| i.state_sample.as_ref());
+            self.invert_loop.tick(state);
+        }
         if let Some(instr) = &mut self.instr {
-            instr.tick();
+            instr.tick(module);
         } else if self.current.has_note_delay() {
-            self.tick_effects(current_tick);
+            self.tick_effects(module, current_tick);
             self.tickn_update_instr();
             return;
         }
         self.tick_volume_effects();
-        self.tick_effects(current_tick);
+        self.tick_effects(module, current_tick);
         self.tickn_update_instr();
     }
 
-    fn tick0_effects(&mut self) {
+    fn tick0_effects(&mut self, module: &Module) {
         match self.current.effect_type {
             0x0 => self
                 .arpeggio
@@ -421,11 +809,13 @@ impl<'a> Channel<'a> {
                 /* 5xy: Tone portamento + Volume slide */
                 self.volume_slide
                     .xm_update_effect(self.current.effect_parameter, 0, 64.0);
+                self.tick0_fast_volume_slide();
             }
             0x6 => {
                 /* 6xy: Vibrato + Volume slide */
                 self.volume_slide
                     .xm_update_effect(self.current.effect_parameter, 0, 64.0);
+                self.tick0_fast_volume_slide();
             }
             0x7 => self
                 .tremolo
@@ -448,6 +838,7 @@ impl<'a> Channel<'a> {
                 /* Axy: Volume slide */
                 self.volume_slide
                     .xm_update_effect(self.current.effect_parameter, 0, 64.0);
+                self.tick0_fast_volume_slide();
             }
             0xC => {
                 /* Cxx: Set volume */
@@ -468,6 +859,7 @@ impl<'a> Channel<'a> {
                             1.0,
                         );
                         self.period = self.portamento_fine_up.clamp(self.period);
+                        self.protracker_period_limit(true);
                     }
                     0x2 => {
                         /* E2y: Fine portamento down */
@@ -477,6 +869,7 @@ impl<'a> Channel<'a> {
                             0.0,
                         );
                         self.period = self.portamento_fine_down.clamp(self.period);
+                        self.protracker_period_limit(false);
                     }
                     0x3 => {
                         /* E3y: Set glissando control */
@@ -484,11 +877,7 @@ impl<'a> Channel<'a> {
                     }
                     0x4 => {
                         /* E4y: Set vibrato control */
-                        // TODO: more abstraction to be done one day here!
-                        self.vibrato.data.waveform = self.current.effect_parameter & 3;
-                        if ((self.current.effect_parameter >> 2) & 1) == 0 {
-                            self.vibrato.retrigger();
-                        }
+                        self.vibrato.set_waveform(self.current.effect_parameter);
                     }
                     0x5 => {
                         /* E5y: Set finetune */
@@ -505,17 +894,19 @@ impl<'a> Channel<'a> {
                     }
                     0x7 => {
                         /* E7y: Set tremolo control */
-                        self.tremolo.data.waveform = self.current.effect_parameter & 3;
-                        if ((self.current.effect_parameter >> 2) & 1) == 0 {
-                            self.tremolo.retrigger();
-                        }
+                        self.tremolo.set_waveform(self.current.effect_parameter);
+                    }
+                    0x8 if self.compatibility == Compatibility::ProTracker => {
+                        /* E8x: Coarse panning */
+                        self.panning =
+                            ((self.current.effect_parameter & 0x0F) * 0x11) as f32 / 256.0;
                     }
                     0x9 => {
                         /* E90: Retrigger note */
                         if self.current.effect_parameter & 0x0F == 0 {
-                            self.trigger_note(TRIGGER_KEEP_VOLUME);
+                            self.retrigger_note(TRIGGER_KEEP_VOLUME);
                             if let Some(instr) = &mut self.instr {
-                                instr.tick();
+                                instr.tick(module);
                             }
                         }
                     }
@@ -548,20 +939,27 @@ impl<'a> Channel<'a> {
                                 );
                             } else if self.current.note.is_keyoff() {
                                 if self.current.instrument == 0 {
-                                    self.key_off(0);
+                                    self.key_off(module, 0);
                                 } else {
                                     self.trigger_note(TRIGGER_KEEP_PERIOD | TRIGGER_KEEP_ENVELOPE);
+                                    self.note_off = true;
                                 }
                             }
                         }
                     }
+                    0xF if self.compatibility == Compatibility::ProTracker => {
+                        /* EFx: Invert loop */
+                        let state = self.instr.as_ref().and_then(|i| i.state_sample.as_ref());
+                        self.invert_loop
+                            .set_speed(self.current.effect_parameter, state);
+                    }
                     _ => {}
                 }
             }
             0x14 => {
                 /* Kxx: Key off */
                 if 0 == self.current.effect_parameter as u16 {
-                    self.key_off(0);
+                    self.key_off(module, 0);
                 }
             }
             0x15 => {
@@ -581,14 +979,18 @@ impl<'a> Channel<'a> {
             }
             0x1B => {
                 /* Rxy: Multi retrig note */
-                self.multi_retrig_note
-                    .xm_update_effect(self.current.effect_parameter, 0, 0.0);
+                if matches!(self.compatibility, Compatibility::ScreamTracker { .. }) {
+                    self.multi_retrig_note
+                        .s3m_update_effect(self.current.effect_parameter);
+                } else {
+                    self.multi_retrig_note
+                        .xm_update_effect(self.current.effect_parameter, 0, 0.0);
+                }
             }
             0x1D => {
                 /* Txy: Tremor */
-                if self.current.effect_parameter > 0 {
-                    self.tremor_param = self.current.effect_parameter;
-                }
+                self.tremor
+                    .xm_update_effect(self.current.effect_parameter, 0, 0.0);
             }
             0x21 => {
                 /* Xxy: Extra stuff */
@@ -612,6 +1014,9 @@ impl<'a> Channel<'a> {
                     self.period = self.portamento_extrafine_down.clamp(self.period);
                 }
             }
+            0x22 => self
+                .panbrello
+                .xm_update_effect(self.current.effect_parameter, 0, 0.0),
             _ => {}
         }
     }
@@ -642,7 +1047,7 @@ impl<'a> Channel<'a> {
             // S - Vibrato speed (0..15)
             0xA => self.vibrato.xm_update_effect(self.current.volume, 1, 0.0),
             // V - Vibrato depth (0..15)
-            0xB => {} // see tick() fn
+            0xB => self.vibrato.xm_update_effect(self.current.volume, 2, 0.0), // see tick() fn
             // P - Set panning
             0xC => self.panning = (self.current.volume & 0x0F) as f32 / 16.0,
             0xD => {
@@ -669,51 +1074,54 @@ impl<'a> Channel<'a> {
     }
 
     /// Change instr and return true if it was the same
-    fn tick0_change_instr(&mut self, sample_only: bool) -> bool {
+    fn tick0_change_instr(&mut self, module: &Module, sample_only: bool) -> bool {
         let instrnr = self.current.instrument as usize - 1;
 
-        if let InstrumentType::Default(id) = &self.module.instrument[instrnr].instr_type {
+        if let InstrumentType::Default(id) = &module.instrument[instrnr].instr_type {
             let was_same = self.instr.as_ref().map_or(false, |i| i.num == instrnr);
 
             // Only proceed if the instrument has samples
             if !id.sample.is_empty() {
                 if sample_only {
                     if let Some(i) = &mut self.instr {
-                        i.replace_instr(id);
+                        i.replace_instr(instrnr);
                     }
                 } else {
+                    self.fade_out_voice();
                     self.instr = Some(StateInstrDefault::new(
                         id,
                         instrnr,
-                        self.period_helper.clone(),
+                        module.frequency_type,
                         self.rate,
                     ));
                 }
             }
 
-            return was_same;
+            was_same
         } else {
             // TODO
-            return false;
+            false
         }
     }
 
     /// Return true if it was the same instrument
-    fn tick0_load_instrument(&mut self) -> bool {
+    fn tick0_load_instrument(&mut self, module: &Module) -> bool {
         if self.current.instrument == 0 {
             return true; // No instrument to load
         }
+        self.invert_loop.restart();
 
-        if self.current.instrument as usize > self.module.instrument.len() {
+        if self.current.instrument as usize > module.instrument.len() {
             /* Invalid instrument, cut current note */
             self.cut_note();
+            self.fade_out_voice();
             self.instr = None;
             return false;
         }
 
         if self.current.has_tone_portamento() {
             self.trigger_note(TRIGGER_KEEP_PERIOD | TRIGGER_KEEP_SAMPLE_POSITION);
-            return self.tick0_change_instr(true);
+            return self.tick0_change_instr(module, true);
         }
 
         if self.current.note.is_none() {
@@ -725,31 +1133,38 @@ impl<'a> Channel<'a> {
                 TRIGGER_KEEP_SAMPLE_POSITION | TRIGGER_KEEP_VOLUME | TRIGGER_KEEP_PERIOD
             };
             self.trigger_note(trigger_flags);
-            return self.tick0_change_instr(true);
+            return self.tick0_change_instr(module, true);
         }
 
         if self.current.note.is_keyoff() {
             self.trigger_note(TRIGGER_KEEP_PERIOD);
+            self.note_off = true;
             return true; // Keyoff does not change instrument
         }
 
-        return self.tick0_change_instr(false);
+        self.tick0_change_instr(module, false)
     }
 
-    fn tick0_load_note(&mut self, new_instr: bool) {
+    fn tick0_load_note(&mut self, module: &Module, new_instr: bool) {
 
         // Note is note valid? Return early.
         if !self.current.note.is_valid() {
             if self.current.note.is_keyoff() {
                 if self.current.instrument == 0 || new_instr {
-                    self.key_off(0);
+                    self.key_off(module, 0);
                 } else {
                     self.trigger_note(TRIGGER_KEEP_PERIOD | TRIGGER_KEEP_ENVELOPE);
+                    self.note_off = true;
                 }
             }
             return;
         }
     
+        // A new note replaces the voice, unless it is a portamento
+        if !self.current.has_tone_portamento() {
+            self.fade_out_voice();
+        }
+
         // Instr?
         if let Some(instr) = &mut self.instr {
             // Portamento?
@@ -765,7 +1180,7 @@ impl<'a> Channel<'a> {
             }
     
             // SetNote
-            if instr.set_note(self.current.note) {
+            if instr.set_note(module, self.current.note) {
                 if let Some(s) = &instr.state_sample {
                     self.note = self.current.note.value() as f32 - 1.0 + s.get_finetuned_note();
                 }
@@ -777,6 +1192,8 @@ impl<'a> Channel<'a> {
                     TRIGGER_KEEP_VOLUME
                 };
                 self.trigger_note(trigger_flag);
+                self.last_note = self.current.note;
+                self.note_on = true;
                 return;
             }
         }
@@ -784,7 +1201,7 @@ impl<'a> Channel<'a> {
         self.cut_note();
     }
 
-    fn tick0_load_instrument_and_note(&mut self) {
+    fn tick0_load_instrument_and_note(&mut self, module: &Module) {
         if let Some(_hhelper) = &self.historical {
             if self.current.effect_type == 0x14 {
                 // Historical Kxy effect bug
@@ -793,30 +1210,45 @@ impl<'a> Channel<'a> {
         }
 
         // First, load instr
-        let new_instr: bool = self.tick0_load_instrument();
+        let new_instr: bool = self.tick0_load_instrument(module);
         // Next, choose sample from note
-        self.tick0_load_note(new_instr);
+        self.tick0_load_note(module, new_instr);
     }
 
-    pub(crate) fn tick0(&mut self, pattern_slot: &PatternSlot) {
+    pub(crate) fn tick0(&mut self, module: &Module, pattern_slot: &PatternSlot) {
         self.current = pattern_slot.clone();
+        if matches!(self.compatibility, Compatibility::ScreamTracker { .. }) {
+            self.s3m_memory.update(&mut self.current);
+        }
 
         if !self.current.has_note_delay()
             || (self.current.has_note_delay() && self.current.effect_parameter & 0x0F == 0)
         {
             /* load instrument then note */
-            self.tick0_load_instrument_and_note();
+            self.tick0_load_instrument_and_note(module);
             // Volume effect
             self.tick0_volume_effects();
             // Effects
-            self.tick0_effects();
+            self.tick0_effects(module);
 
             if self.arpeggio.in_progress() && !self.current.has_arpeggio() {
                 self.arpeggio.retrigger();
             }
 
             if self.vibrato.in_progress() && !self.current.has_vibrato() {
-                self.vibrato.retrigger();
+                self.vibrato.stop();
+            }
+
+            if self.panbrello.in_progress() && self.current.effect_type != 0x22 {
+                self.panbrello.stop();
+            }
+
+            // FT2 keeps the tremor state, even muted, until the next note
+            if self.tremor.in_progress()
+                && self.current.effect_type != 0x1D
+                && self.historical.is_none()
+            {
+                self.tremor.retrigger();
             }
 
             self.tickn_update_instr();
@@ -824,19 +1256,79 @@ impl<'a> Channel<'a> {
             self.note_delay_param = self.current.effect_parameter & 0x0F;
         }
     }
-}
 
-impl<'a> Iterator for Channel<'a> {
-    type Item = (f32, f32);
+    /// Play `note` with the module instrument `instrument` as if it was on a row without any
+    /// effect, then set the channel volume (0.0 to 1.0). Returns false if nothing can be played.
+    pub(crate) fn note_on(
+        &mut self,
+        module: &Module,
+        instrument: usize,
+        note: Note,
+        volume: f32,
+    ) -> bool {
+        let slot_instrument = match u8::try_from(instrument + 1) {
+            Ok(i) if note.is_valid() => i,
+            _ => return false,
+        };
+        if instr_default(module, instrument).is_none_or(|id| id.sample.is_empty()) {
+            return false;
+        }
+        let slot = PatternSlot {
+            note,
+            instrument: slot_instrument,
+            ..Default::default()
+        };
+        self.tick0(module, &slot);
+        self.volume = volume.clamp(0.0, 1.0);
+        self.tickn_update_instr();
+        self.playing_note().is_some()
+    }
 
-    // Was next_of_sample()
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Release the note, as a key off on a row without any effect
+    pub(crate) fn note_off(&mut self, module: &Module) {
+        let slot = PatternSlot {
+            note: Note::KeyOff,
+            ..Default::default()
+        };
+        self.tick0(module, &slot);
+    }
+
+    /// Returns the next (left, right) frame, `sample` must be the one returned by `sample_ref()`
+    pub(crate) fn next_frame(
+        &mut self,
+        sample: &Sample,
+        interpolator: &Interpolator,
+    ) -> Option<(f32, f32)> {
         match &mut self.instr {
-            Some(i) => match i.next() {
-                Some(fval) => Some((fval * self.actual_volume[0], fval * self.actual_volume[1])),
+            Some(i) => match i.next(sample, &self.invert_loop, interpolator) {
+                Some(fval) => {
+                    let [left, right] = self.ramp.next();
+                    Some((fval * left, fval * right))
+                }
                 None => None,
             },
             None => None,
         }
     }
+
+    /// Returns the next (left, right) frame of the voice fading out, `sample` must be the one
+    /// returned by `fadeout_sample_ref()`
+    pub(crate) fn next_fadeout_frame(
+        &mut self,
+        sample: &Sample,
+        interpolator: &Interpolator,
+    ) -> Option<(f32, f32)> {
+        let (s, ramp) = self.fadeout.as_mut()?;
+        let frame = match s.next(sample, interpolator) {
+            Some(fval) => {
+                let [left, right] = ramp.next();
+                Some((fval * left, fval * right))
+            }
+            None => None,
+        };
+        if frame.is_none() || ramp.is_done() {
+            self.fadeout = None;
+        }
+        frame
+    }
 }
@@ -0,0 +1,99 @@
+/// Read-only channel snapshot for trackers and visualizers, see `XmrsPlayer::channel_info()`
+use xmrs::prelude::*;
+
+/// Where a sample is read
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LoopDirection {
+    /// No sample playing
+    #[default]
+    Stopped,
+    /// Playing forward
+    Forward,
+    /// Playing backward, in a ping-pong loop
+    Backward,
+}
+
+/// Effects running on the current row, from the effect and volume columns
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ActiveEffects {
+    pub arpeggio: bool,
+    /// Portamento up or down, fine and extra fine included
+    pub portamento: bool,
+    pub tone_portamento: bool,
+    pub vibrato: bool,
+    pub tremolo: bool,
+    /// Tremor, `ChannelInfo::volume` is 0.0 while it is off
+    pub tremor: bool,
+    /// Panbrello, included in `ChannelInfo::panning`
+    pub panbrello: bool,
+    /// Volume slide, fine included
+    pub volume_slide: bool,
+    pub panning_slide: bool,
+    /// Retrigger and multi retrigger
+    pub retrigger: bool,
+    pub note_cut: bool,
+    pub note_delay: bool,
+    pub key_off: bool,
+}
+
+impl ActiveEffects {
+    pub(crate) fn new(slot: &PatternSlot) -> Self {
+        let param = slot.effect_parameter;
+        let extended = |x: u8| slot.effect_type == 0xE && param >> 4 == x;
+        let volume = slot.volume >> 4;
+        Self {
+            arpeggio: slot.has_arpeggio(),
+            portamento: matches!(slot.effect_type, 1 | 2)
+                || extended(0x1)
+                || extended(0x2)
+                || (slot.effect_type == 0x21 && matches!(param >> 4, 1 | 2)),
+            tone_portamento: slot.has_tone_portamento(),
+            vibrato: slot.has_vibrato(),
+            tremolo: slot.effect_type == 7,
+            tremor: slot.effect_type == 0x1D,
+            panbrello: slot.effect_type == 0x22,
+            volume_slide: slot.has_volume_slide() || matches!(volume, 0x6..=0x9),
+            panning_slide: slot.effect_type == 0x19 || matches!(volume, 0xD | 0xE),
+            retrigger: extended(0x9) || slot.effect_type == 0x1B,
+            note_cut: extended(0xC),
+            note_delay: slot.has_note_delay(),
+            key_off: slot.effect_type == 0x14,
+        }
+    }
+}
+
+/// State of a channel after the last tick
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelInfo {
+    /// Instrument index in the module
+    pub instrument: Option<usize>,
+    /// Sample index in the instrument
+    pub sample: Option<usize>,
+    /// Last note triggered, if its voice is still playing
+    pub note: Option<Note>,
+    /// Note heard, in semitones from C-0, with slides, arpeggio and vibrato (0.0 if nothing plays)
+    pub pitch: f32,
+    /// Sample playback frequency in Hz (0.0 if nothing plays)
+    pub frequency: f32,
+    /// Final volume, after envelope, fadeout, tremolo, tremor and mixer volume (0.0 to 1.0)
+    pub volume: f32,
+    /// Final panning, after panbrello, envelope and mixer override (0.0 is left, 1.0 is right)
+    pub panning: f32,
+    /// Volume envelope position in ticks, if the instrument has one
+    pub volume_envelope: Option<usize>,
+    /// Panning envelope position in ticks, if the instrument has one
+    pub panning_envelope: Option<usize>,
+    /// Fadeout volume, 1.0 until the key is released
+    pub fadeout: f32,
+    /// True if the key was released
+    pub released: bool,
+    /// Sample position in sample points
+    pub position: Option<f32>,
+    pub direction: LoopDirection,
+    /// Raw effect type and parameter of the current row
+    pub effect: (u8, u8),
+    /// Raw volume column of the current row
+    pub volume_column: u8,
+    pub effects: ActiveEffects,
+    pub muted: bool,
+}
@@ -0,0 +1,95 @@
+/// Channel levels for VU meters and oscilloscopes, see `XmrsPlayer::set_metering()`
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+use xmrs::prelude::*;
+
+/// Levels of a channel over the last rendered block, before global volume and amplification
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelMeter {
+    /// Highest absolute value, both sides
+    pub peak: f32,
+    /// Root mean square, both sides
+    pub rms: f32,
+    /// True if a voice is playing
+    pub active: bool,
+    /// Last note triggered, if a voice is playing
+    pub note: Option<Note>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MeterState {
+    peak: f32,
+    sum: f32,
+    frames: usize,
+    active: bool,
+    note: Option<Note>,
+    /// Recent (left, right) frames, `scope_pos` is the oldest one
+    scope: Vec<(f32, f32)>,
+    scope_pos: usize,
+}
+
+impl MeterState {
+    pub(crate) fn new(scope_frames: usize) -> Self {
+        Self {
+            scope: vec![(0.0, 0.0); scope_frames],
+            ..Default::default()
+        }
+    }
+
+    /// Forget the previous block
+    pub(crate) fn start_block(&mut self) {
+        self.peak = 0.0;
+        self.sum = 0.0;
+        self.frames = 0;
+    }
+
+    #[inline(always)]
+    pub(crate) fn add(&mut self, left: f32, right: f32) {
+        self.peak = self.peak.max(left.abs()).max(right.abs());
+        self.sum += left * left + right * right;
+        self.frames += 1;
+        if !self.scope.is_empty() {
+            self.scope[self.scope_pos] = (left, right);
+            self.scope_pos = (self.scope_pos + 1) % self.scope.len();
+        }
+    }
+
+    /// Frames where the channel was silent or not mixed
+    pub(crate) fn add_silence(&mut self, frames: usize) {
+        self.frames += frames;
+        if !self.scope.is_empty() {
+            for _ in 0..frames.min(self.scope.len()) {
+                self.scope[self.scope_pos] = (0.0, 0.0);
+                self.scope_pos = (self.scope_pos + 1) % self.scope.len();
+            }
+        }
+    }
+
+    pub(crate) fn set_voice(&mut self, note: Option<Note>) {
+        self.active = note.is_some();
+        self.note = note;
+    }
+
+    pub(crate) fn meter(&self) -> ChannelMeter {
+        ChannelMeter {
+            peak: self.peak,
+            rms: if self.sum <= 0.0 {
+                0.0
+            } else {
+                (self.sum / (2 * self.frames) as f32).sqrt()
+            },
+            active: self.active,
+            note: self.note,
+        }
+    }
+
+    /// Recent frames, oldest first
+    pub(crate) fn scope(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        let (newest, oldest) = self.scope.split_at(self.scope_pos);
+        oldest.iter().chain(newest.iter()).copied()
+    }
+}
@@ -0,0 +1,38 @@
+/// Tracker whose effect semantics are followed, see `XmrsPlayer::set_compatibility()`
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use xmrs::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Compatibility {
+    /// FastTracker II
+    #[default]
+    Xm,
+    /// ProTracker: E8x coarse panning, EFx invert loop, periods limited to 113..856
+    ProTracker,
+    /// Scream Tracker 3: memory shared by Dxy, Exx, Fxx and other effects, Qxy volume changes,
+    /// Ixy tremor, and volume slides on the first tick of the row too if `fast_volume_slides`
+    /// (Scream Tracker 3.00, or the fast volume slides flag). Other effects are played as
+    /// converted to XM by `import_s3m`
+    ScreamTracker { fast_volume_slides: bool },
+}
+
+impl Compatibility {
+    /// Best guess for `module`, not applied by the player. xmrs doesn't record the original
+    /// format: modules loaded by `import_amiga` and `import_s3m` are recognized by their
+    /// "XmRs reader" comment, then by their Amiga or linear frequencies. The S3M fast volume
+    /// slides flag isn't imported, so it is always guessed off: set it with
+    /// `XmrsPlayer::set_compatibility()` if you know it.
+    pub fn guess(module: &Module) -> Self {
+        if module.comment != "XmRs reader" {
+            return Self::Xm;
+        }
+        match module.frequency_type {
+            FrequencyType::AmigaFrequencies => Self::ProTracker,
+            FrequencyType::LinearFrequencies => Self::ScreamTracker {
+                fast_volume_slides: false,
+            },
+        }
+    }
+}
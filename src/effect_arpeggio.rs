@@ -1,14 +1,18 @@
 use crate::effect::{EffectPlugin, EffectXM2EffectPlugin};
 use crate::historical_helper::HistoricalHelper;
 use core::default::Default;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Arpeggio {
     offset1: f32,
     offset2: f32,
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectArpeggio {
     data: Arpeggio,
     historical: Option<HistoricalHelper>,
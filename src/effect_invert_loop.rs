@@ -0,0 +1,219 @@
+/* EFx: Invert loop (also called funk repeat)
+
+ProTracker adds a speed taken from a table to a counter at every tick except the first of a row.
+When the counter reaches 128, the next point of the sample loop is negated, in the sample data.
+The effect keeps running on the next rows, until EF0.
+
+The module is shared and never modified: inverted points are kept by the channel for the sample
+it plays, and forgotten when it plays another sample. The bitmap of inverted points is allocated
+with the channel, for the largest loop of the module: nothing is allocated while playing.
+*/
+use crate::state_instr_default::instr_default;
+use crate::state_sample::StateSample;
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use xmrs::prelude::*;
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EffectInvertLoop {
+    /// Index in `FUNK_TABLE`, 0 stops the effect
+    speed: u8,
+    /// A point is inverted when it reaches 128
+    counter: u8,
+    /// (instrument, sample) whose loop is inverted
+    sample: Option<(usize, usize)>,
+    loop_start: usize,
+    loop_length: usize,
+    /// Offset in the loop of the last inverted point
+    position: usize,
+    /// One bit per loop point, set if the point is inverted, for the largest loop of the module
+    inverted: Vec<u32>,
+    /// Some bit of `inverted` is set
+    any_inverted: bool,
+}
+
+impl EffectInvertLoop {
+    const FUNK_TABLE: [u8; 16] = [0, 5, 6, 7, 8, 10, 11, 13, 16, 19, 22, 26, 32, 43, 64, 128];
+
+    pub fn new(module: &Module) -> Self {
+        let largest_loop = (0..module.instrument.len())
+            .filter_map(|i| instr_default(module, i))
+            .flat_map(|id| id.sample.iter())
+            .map(|s| s.loop_length as usize)
+            .max()
+            .unwrap_or(0);
+        Self {
+            inverted: vec![0; largest_loop.div_ceil(32)],
+            ..Default::default()
+        }
+    }
+
+    /// EFx, at the first tick of the row
+    pub fn set_speed(&mut self, speed: u8, state: Option<&StateSample>) {
+        self.speed = speed & 0x0F;
+        self.tick(state);
+    }
+
+    /// A new instrument restarts from the loop start, inverted points are kept
+    pub fn restart(&mut self) {
+        self.position = 0;
+    }
+
+    /// Every tick except the first of a row, `state` is the sample played
+    pub fn tick(&mut self, state: Option<&StateSample>) {
+        if self.speed == 0 {
+            return;
+        }
+        self.counter += Self::FUNK_TABLE[self.speed as usize];
+        if self.counter < 128 {
+            return;
+        }
+        self.counter = 0;
+        // The loop is followed when the note is triggered, see `select()`
+        let key = state.map(|s| (s.get_instrument_num(), s.get_sample_num()));
+        if key.is_none() || self.sample != key || self.loop_length == 0 {
+            return;
+        }
+        self.position += 1;
+        if self.position >= self.loop_length {
+            self.position = 0;
+        }
+        self.inverted[self.position / 32] ^= 1 << (self.position % 32);
+        self.any_inverted = self.used().iter().any(|&bits| bits != 0);
+    }
+
+    /// Follow the loop of `state` when a note is triggered, forgetting inverted points if the
+    /// sample changed
+    pub fn select(&mut self, state: &StateSample) {
+        let key = Some((state.get_instrument_num(), state.get_sample_num()));
+        if self.sample == key {
+            return;
+        }
+        let (loop_start, loop_length) = state.get_loop().unwrap_or((0, 0));
+        self.inverted[..self.loop_length.div_ceil(32)].fill(0);
+        self.sample = key;
+        self.loop_start = loop_start;
+        // A loop out of the module can't be inverted
+        self.loop_length = if loop_length.div_ceil(32) <= self.inverted.len() {
+            loop_length
+        } else {
+            0
+        };
+        self.position = 0;
+        self.any_inverted = false;
+    }
+
+    /// Words of the bitmap used by the loop
+    fn used(&self) -> &[u32] {
+        &self.inverted[..self.loop_length.div_ceil(32)]
+    }
+
+    /// Returns true if the counters and the bitmap are consistent, for snapshots
+    pub fn is_valid(&self) -> bool {
+        (self.speed as usize) < Self::FUNK_TABLE.len()
+            && self.counter < 128
+            && self.loop_length.div_ceil(32) <= self.inverted.len()
+            && (self.position < self.loop_length || self.position == 0)
+            && self.any_inverted == self.used().iter().any(|&bits| bits != 0)
+            && self.inverted[self.loop_length.div_ceil(32)..]
+                .iter()
+                .all(|&bits| bits == 0)
+    }
+
+    /// Returns self if some points of the sample played by `state` are inverted
+    pub fn overlay(&self, state: &StateSample) -> Option<&Self> {
+        let key = (state.get_instrument_num(), state.get_sample_num());
+        (self.any_inverted && self.sample == Some(key)).then_some(self)
+    }
+
+    /// Point `index` of `sample`, negated like ProTracker does (-1 - x for 8-bit data) if inverted
+    pub fn at(&self, sample: &Sample, index: usize) -> f32 {
+        let value = sample.at(index);
+        let offset = index.wrapping_sub(self.loop_start);
+        if offset >= self.loop_length || (self.inverted[offset / 32] >> (offset % 32)) & 1 == 0 {
+            return value;
+        }
+        match sample.data {
+            SampleDataType::Mono8(_) | SampleDataType::Stereo8(_) => -value - 1.0 / 128.0,
+            _ => -value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use xmrs::instr_default::InstrDefault;
+
+    fn sample(loop_length: u32) -> Sample {
+        Sample {
+            name: String::new(),
+            loop_start: 0,
+            loop_length,
+            volume: 1.0,
+            finetune: 0.0,
+            flags: LoopType::Forward,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Mono8(vec![64; loop_length as usize]),
+        }
+    }
+
+    /// One instrument with a loop of 40 points and a loop of 100 points
+    fn module() -> Module {
+        let id = InstrDefault {
+            sample: vec![sample(40), sample(100)],
+            ..Default::default()
+        };
+        Module {
+            instrument: vec![Instrument {
+                instr_type: InstrumentType::Default(id),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ticks_only_follow_the_triggered_sample() {
+        let module = module();
+        let looped = sample(40);
+        let state = StateSample::new(&looped, 0, 0, 44100.0);
+        let mut invert = EffectInvertLoop::new(&module);
+        let bitmap = invert.inverted.as_ptr();
+        assert_eq!(invert.inverted.len(), 4);
+        invert.select(&state);
+        invert.set_speed(0xF, Some(&state));
+        assert!(invert.overlay(&state).is_some());
+
+        // Every point inverted, then back: two turns of the loop
+        for _ in 1..80 {
+            invert.tick(Some(&state));
+            assert!(invert.is_valid());
+        }
+        assert!(invert.overlay(&state).is_none());
+        invert.tick(Some(&state));
+        assert!(invert.overlay(&state).is_some());
+
+        // Another sample is not triggered yet: nothing inverted
+        let other = sample(100);
+        let other_state = StateSample::new(&other, 0, 1, 44100.0);
+        invert.tick(Some(&other_state));
+        assert!(invert.overlay(&other_state).is_none());
+        assert!(invert.is_valid());
+
+        // Triggered: the previous points are forgotten, in the same bitmap
+        invert.select(&other_state);
+        assert!(invert.inverted.iter().all(|&bits| bits == 0));
+        for _ in 0..100 {
+            invert.tick(Some(&other_state));
+            assert!(invert.is_valid());
+        }
+        assert!(invert.overlay(&other_state).is_some());
+        assert_eq!(invert.inverted.as_ptr(), bitmap);
+        assert_eq!(invert.inverted.len(), 4);
+    }
+}
@@ -7,8 +7,11 @@ use core::default::Default;
 use micromath::F32Ext;
 #[cfg(feature = "libm")]
 use num_traits::float::Float;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MultiRetrigNote {
     note_retrig_speed: f32,
     note_retrig_vol: f32,
@@ -26,6 +29,7 @@ impl MultiRetrigNote {
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectMultiRetrigNote {
     data: MultiRetrigNote,
     historical: Option<HistoricalHelper>,
@@ -43,6 +47,28 @@ impl EffectMultiRetrigNote {
             ..Default::default()
         }
     }
+
+    /// S3M Qxy: x is kept even if 0, ST3 has no memory by nibble
+    pub fn s3m_update_effect(&mut self, param: u8) {
+        self.xm_update_effect(param, 0, 0.0);
+        self.data.note_retrig_vol = (param >> 4) as f32 / 16.0;
+    }
+
+    /// S3M Qxy: volume after a retrigger, x changes it in 1/64 steps
+    pub fn s3m_volume(&self, vol: f32) -> f32 {
+        let vol = vol * 64.0;
+        let mut v = match (16.0 * self.data.note_retrig_vol) as u8 {
+            rv @ 1..=5 => vol - (1 << (rv - 1)) as f32,
+            6 => vol * 2.0 / 3.0,
+            7 => vol / 2.0,
+            rv @ 9..=13 => vol + (1 << (rv - 9)) as f32,
+            14 => vol * 3.0 / 2.0,
+            15 => vol * 2.0,
+            _ => vol,
+        } / 64.0;
+        clamp(&mut v);
+        v
+    }
 }
 
 impl EffectPlugin for EffectMultiRetrigNote {
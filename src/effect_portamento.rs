@@ -1,8 +1,11 @@
 use core::default::Default;
 
 use crate::effect::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectPortamento {
     speed: f32,
 }
@@ -0,0 +1,173 @@
+/* S3M shared effect memory
+
+In Scream Tracker 3, Dxy, Exx, Fxx, Ixy, Jxy, Kxy, Lxy, Qxy, Rxy and Sxy share one parameter
+memory per channel: an effect without parameter plays the last non zero parameter of any of them,
+decoded the way of its own effect. D00 after EF2 is a fine volume slide, E00 after DF2 a fine
+portamento.
+
+`import_s3m` resolves this memory inside each pattern while converting effects to XM, then starts
+again from zero at the next pattern. Rows are seen here in playing order, already converted: the
+S3M parameter is rebuilt from the XM effect, and an effect left without parameter is converted
+again from the memory.
+
+Jxy and Sxy without parameter can't be told from an empty effect once converted: they don't use
+the memory across patterns.
+*/
+use core::default::Default;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use xmrs::prelude::*;
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EffectS3mMemory {
+    /// Last non zero S3M parameter
+    parameter: u8,
+}
+
+impl EffectS3mMemory {
+    /// Remember the parameter of `slot`, or give it the remembered one
+    pub fn update(&mut self, slot: &mut PatternSlot) {
+        if slot.effect_parameter != 0 {
+            if let Some(parameter) = Self::s3m_parameter(slot.effect_type, slot.effect_parameter) {
+                self.parameter = parameter;
+            }
+        } else if self.parameter != 0 {
+            self.recall(slot);
+        }
+    }
+
+    /// S3M parameter of a converted effect sharing the memory
+    fn s3m_parameter(effect_type: u8, param: u8) -> Option<u8> {
+        let x = param & 0x0F;
+        match effect_type {
+            // Dxy, Kxy, Lxy, Fxx, Exx, Ixy, Jxy, Qxy, Rxy
+            0x0 | 0x1 | 0x2 | 0x5 | 0x6 | 0x7 | 0xA | 0x1B | 0x1D => Some(param),
+            0xE => match param >> 4 {
+                // DxF and KxF: fine volume slide up
+                0xA => Some(x << 4 | 0x0F),
+                // DFx and KFx: fine volume slide down, FFx and EFx: fine portamento
+                0x1 | 0x2 | 0xB => Some(0xF0 | x),
+                // Sxy
+                0x3 => Some(0x10 | x),
+                0x5 => Some(0x20 | x),
+                0x4 => Some(0x30 | x),
+                0x7 => Some(0x40 | x),
+                0x6 => Some(0xB0 | x),
+                sub @ 0xC..=0xF => Some(sub << 4 | x),
+                _ => None,
+            },
+            // FEx and EEx: extra fine portamento
+            0x21 => Some(0xE0 | x),
+            _ => None,
+        }
+    }
+
+    /// Convert an effect without parameter again, with the remembered parameter
+    fn recall(&self, slot: &mut PatternSlot) {
+        let param = self.parameter;
+        match slot.effect_type {
+            // Dxy and Kxy
+            0xA | 0x6 => {
+                let fine = if param > 0xF0 {
+                    Some(0xB0 | (param & 0x0F))
+                } else if param & 0x0F == 0x0F && param & 0xF0 != 0 {
+                    Some(0xA0 | (param >> 4))
+                } else {
+                    None
+                };
+                match fine {
+                    Some(fine) => {
+                        // Kxy goes on with the vibrato
+                        if slot.effect_type == 0x6 && slot.volume == 0 {
+                            slot.volume = 0xB0;
+                        }
+                        slot.effect_type = 0xE;
+                        slot.effect_parameter = fine;
+                    }
+                    // The low nibble comes first in ST3
+                    None if param & 0x0F != 0 => slot.effect_parameter = param & 0x0F,
+                    None => slot.effect_parameter = param,
+                }
+            }
+            // Lxy
+            0x5 if param & 0x0F != 0 => slot.effect_parameter = param & 0x0F,
+            // Fxx and Exx
+            0x1 | 0x2 => {
+                let up = slot.effect_type == 0x1;
+                let x = param & 0x0F;
+                let direction = if up { 0x10 } else { 0x20 };
+                match param & 0xF0 {
+                    0xF0 => {
+                        slot.effect_type = 0xE;
+                        slot.effect_parameter = direction | x;
+                    }
+                    0xE0 if x != 0 => {
+                        slot.effect_type = 0x21;
+                        slot.effect_parameter = direction | x;
+                    }
+                    0xE0 => slot.effect_type = 0,
+                    _ => slot.effect_parameter = param,
+                }
+            }
+            // Lxy, Rxy, Qxy and Ixy
+            0x5 | 0x7 | 0x1B | 0x1D => slot.effect_parameter = param,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(effect_type: u8, effect_parameter: u8) -> PatternSlot {
+        PatternSlot {
+            effect_type,
+            effect_parameter,
+            ..Default::default()
+        }
+    }
+
+    /// Effect played by `effect` after `previous`
+    fn after(previous: (u8, u8), effect: (u8, u8)) -> (u8, u8, u8) {
+        let mut memory = EffectS3mMemory::default();
+        memory.update(&mut slot(previous.0, previous.1));
+        let mut slot = slot(effect.0, effect.1);
+        memory.update(&mut slot);
+        (slot.effect_type, slot.effect_parameter, slot.volume)
+    }
+
+    #[test]
+    fn memory_is_shared_and_decoded_by_each_effect() {
+        // D04 then E00, F00, D00
+        assert_eq!(after((0xA, 0x04), (0x2, 0x00)), (0x2, 0x04, 0));
+        assert_eq!(after((0xA, 0x04), (0x1, 0x00)), (0x1, 0x04, 0));
+        assert_eq!(after((0xA, 0x04), (0xA, 0x00)), (0xA, 0x04, 0));
+        // EF2 then D00: fine volume slide down
+        assert_eq!(after((0xE, 0x22), (0xA, 0x00)), (0xE, 0xB2, 0));
+        // D2F then K00: fine volume slide up, with the vibrato
+        assert_eq!(after((0xE, 0xA2), (0x6, 0x00)), (0xE, 0xA2, 0xB0));
+        // DF3 then F00 and E00: fine portamentos
+        assert_eq!(after((0xE, 0xB3), (0x1, 0x00)), (0xE, 0x13, 0));
+        assert_eq!(after((0xE, 0xB3), (0x2, 0x00)), (0xE, 0x23, 0));
+        // FE3 then E00: extra fine portamento down
+        assert_eq!(after((0x21, 0x13), (0x2, 0x00)), (0x21, 0x23, 0));
+        // Q24 then D00: low nibble first
+        assert_eq!(after((0x1B, 0x24), (0xA, 0x00)), (0xA, 0x04, 0));
+        // S34 is remembered too
+        assert_eq!(after((0xE, 0x44), (0x1D, 0x00)), (0x1D, 0x34, 0));
+    }
+
+    #[test]
+    fn other_effects_keep_their_memory() {
+        // Hxy and Gxx have their own memory
+        assert_eq!(after((0x4, 0x44), (0xA, 0x00)), (0xA, 0x00, 0));
+        assert_eq!(after((0xA, 0x04), (0x3, 0x00)), (0x3, 0x00, 0));
+        assert_eq!(after((0xA, 0x04), (0x4, 0x00)), (0x4, 0x00, 0));
+        // An empty slot stays empty
+        assert_eq!(after((0xA, 0x04), (0x0, 0x00)), (0x0, 0x00, 0));
+        // A parameter is kept
+        assert_eq!(after((0xA, 0x04), (0x2, 0x08)), (0x2, 0x08, 0));
+    }
+}
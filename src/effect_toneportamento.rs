@@ -3,9 +3,13 @@ use xmrs::period_helper::{FrequencyType, PeriodHelper};
 use crate::effect::*;
 use crate::helper::*;
 use core::default::Default;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectTonePortamento {
+    #[cfg_attr(feature = "serde", serde(skip))]
     period_helper: PeriodHelper,
     speed: f32,
     goal: f32,
@@ -18,6 +22,10 @@ impl EffectTonePortamento {
             ..Default::default()
         }
     }
+
+    pub fn set_period_helper(&mut self, period_helper: PeriodHelper) {
+        self.period_helper = period_helper;
+    }
 }
 
 impl EffectPlugin for EffectTonePortamento {
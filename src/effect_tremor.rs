@@ -3,54 +3,100 @@
 Rapidly switches the sample volume on and off on every tick of the row except the first.
 Volume is on for x + 1 ticks and off for y + 1 ticks.
 
-tremor_on: bool = [(T-1) % (X+1+Y+1) ] > X
+Counting follows FT2: a phase counter is decremented at each tick, and the phase changes when it
+goes below zero. The counter is not reset by a new row, only by a new note.
+
+S3M Ixy: volume is on for x ticks and off for y ticks, 0 is the same as 1.
 */
 use crate::effect::{EffectPlugin, EffectXM2EffectPlugin};
 use core::default::Default;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectTremor {
-    tick: usize,
-    tick_on: usize,
-    tick_off: usize,
+    /// S3M Ixy semantics
+    s3m: bool,
+    /// Phase counter start values
+    tick_on: u8,
+    tick_off: u8,
+    /// Ticks left in the current phase, minus one
+    counter: i8,
+    /// True during the on phase
+    on: bool,
+    in_progress: bool,
+}
+
+impl EffectTremor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// S3M Ixy tremor
+    pub fn s3m() -> Self {
+        Self {
+            s3m: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_s3m(&self) -> bool {
+        self.s3m
+    }
 }
 
 impl EffectPlugin for EffectTremor {
     fn tick0(&mut self, on: f32, off: f32) -> f32 {
-        self.tick_on = on as usize;
-        self.tick_off = off as usize;
+        self.tick_on = on as u8;
+        self.tick_off = off as u8;
         self.value()
     }
 
     fn tick(&mut self) -> f32 {
-        // tick overflow seems impossible here
-        self.tick = self.tick + 1;
+        self.in_progress = true;
+        self.counter -= 1;
+        if self.counter < 0 {
+            self.on = !self.on;
+            self.counter = if self.on { self.tick_on } else { self.tick_off } as i8;
+        }
         self.value()
     }
 
     fn in_progress(&self) -> bool {
-        (self.tick - 1) % (self.tick_on + 1 + self.tick_off + 1) > self.tick_on
+        self.in_progress
     }
 
     fn retrigger(&mut self) -> f32 {
-        self.tick = 0;
+        self.counter = 0;
+        self.on = false;
+        self.in_progress = false;
         self.value()
     }
 
-    fn clamp(&self, _value: f32) -> f32 {
-        self.value()
+    fn clamp(&self, value: f32) -> f32 {
+        value * self.value()
     }
 
+    /// 1.0 if the volume is on, 0.0 if it is off
     fn value(&self) -> f32 {
-        0.0
+        if !self.in_progress || self.on {
+            1.0
+        } else {
+            0.0
+        }
     }
 }
 
 impl EffectXM2EffectPlugin for EffectTremor {
-    fn xm_convert(param: u8, _special: u8) -> Option<(Option<f32>, Option<f32>)> {
+    /// `special` is 1 for S3M Ixy
+    fn xm_convert(param: u8, special: u8) -> Option<(Option<f32>, Option<f32>)> {
         if param > 0 {
-            let tick_on = param >> 4;
-            let tick_off = param & 0x0F;
+            let (tick_on, tick_off) = if special == 1 {
+                ((param >> 4).max(1) - 1, (param & 0x0F).max(1) - 1)
+            } else {
+                (param >> 4, param & 0x0F)
+            };
             Some((Some(tick_on as f32), Some(tick_off as f32)))
         } else {
             None
@@ -58,9 +104,77 @@ impl EffectXM2EffectPlugin for EffectTremor {
     }
 
     fn xm_update_effect(&mut self, param: u8, _special1: u8, _special2: f32) {
-        if param > 0 {
-            self.tick_on = (param >> 4) as usize;
-            self.tick_off = (param & 0x0F) as usize;
+        if let Some((on, off)) = Self::xm_convert(param, self.s3m as u8) {
+            self.tick0(on.unwrap(), off.unwrap());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gate of `ticks` ticks, '1' when the volume is on
+    fn gate(tremor: &mut EffectTremor, ticks: usize) -> alloc::string::String {
+        (0..ticks)
+            .map(|_| if tremor.tick() == 1.0 { '1' } else { '0' })
+            .collect()
+    }
+
+    #[test]
+    fn xm_on_x_plus_one_off_y_plus_one() {
+        let mut tremor = EffectTremor::new();
+        tremor.xm_update_effect(0x21, 0, 0.0);
+        assert_eq!(tremor.value(), 1.0);
+        assert_eq!(gate(&mut tremor, 11), "11100111001");
+
+        let mut tremor = EffectTremor::new();
+        tremor.xm_update_effect(0x00, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 6), "101010");
+    }
+
+    #[test]
+    fn xm_parameter_memory() {
+        let mut tremor = EffectTremor::new();
+        tremor.xm_update_effect(0x12, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 3), "110");
+        tremor.xm_update_effect(0x00, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 7), "0011000");
+    }
+
+    #[test]
+    fn ft2_counter_runs_across_rows() {
+        // T13 on a first row of 4 ticks, then T31: the off phase started with T13 ends first
+        let mut tremor = EffectTremor::new();
+        tremor.xm_update_effect(0x13, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 3), "110");
+        tremor.xm_update_effect(0x31, 0, 0.0);
+        assert_eq!(tremor.value(), 0.0);
+        assert_eq!(gate(&mut tremor, 9), "000111100");
+    }
+
+    #[test]
+    fn retrigger_starts_on() {
+        let mut tremor = EffectTremor::new();
+        tremor.xm_update_effect(0x11, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 3), "110");
+        tremor.retrigger();
+        assert!(!tremor.in_progress());
+        assert_eq!(tremor.value(), 1.0);
+        assert_eq!(gate(&mut tremor, 4), "1100");
+    }
+
+    #[test]
+    fn s3m_on_x_off_y() {
+        let mut tremor = EffectTremor::s3m();
+        tremor.xm_update_effect(0x21, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 9), "110110110");
+
+        // 0 is the same as 1
+        let mut tremor = EffectTremor::s3m();
+        tremor.xm_update_effect(0x30, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 8), "11101110");
+        tremor.xm_update_effect(0x00, 0, 0.0);
+        assert_eq!(gate(&mut tremor, 4), "1110");
+    }
+}
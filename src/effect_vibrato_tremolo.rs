@@ -6,27 +6,38 @@ use micromath::F32Ext;
 use num_traits::float::Float;
 
 use crate::effect::*;
+use crate::rng::Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VibratoTremolo {
+    /// Bits 0-1: 0 sine, 1 ramp down, 2 square, 3 random. Bit 2: don't retrigger on new note
     pub waveform: u8,
     speed: f32,
     depth: f32,
+    /// FT2 plays the random waveform as a square
+    historical: bool,
+    rng: Rng,
 }
 
 impl VibratoTremolo {
+    pub const WAVEFORM_NO_RETRIGGER: u8 = 4;
+
     pub fn new(waveform: u8, speed: f32, depth: f32) -> Self {
         Self {
             waveform,
             speed,
             depth,
+            ..Default::default()
         }
     }
 
     // return depth * (-1..1)
-    fn waveform(&self, pos: f32) -> f32 {
+    fn waveform(&mut self, pos: f32) -> f32 {
         let value = self.depth
-            * match self.waveform {
+            * match self.waveform & 3 {
                 0 => -(core::f32::consts::TAU * pos).sin(),
                 1 => {
                     // Ramp down
@@ -36,6 +47,7 @@ impl VibratoTremolo {
                         -2.0 * pos + 2.0
                     }
                 }
+                3 if !self.historical => self.rng.next_f32(),
                 _ => {
                     // square
                     if pos < 0.5 {
@@ -51,6 +63,7 @@ impl VibratoTremolo {
 }
 
 #[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectVibratoTremolo {
     pub data: VibratoTremolo,
     multiplier: f32,
@@ -70,15 +83,42 @@ impl EffectVibratoTremolo {
         }
     }
 
-    pub fn tremolo() -> Self {
-        Self::new(VibratoTremolo::default(), 1.0)
+    fn with_historical(mut self, historical: bool) -> Self {
+        self.data.historical = historical;
+        self
+    }
+
+    pub fn tremolo(historical: bool) -> Self {
+        Self::new(VibratoTremolo::default(), 1.0).with_historical(historical)
     }
 
-    pub fn vibrato(period_helper: &PeriodHelper) -> Self {
+    pub fn vibrato(period_helper: &PeriodHelper, historical: bool) -> Self {
         match period_helper.freq_type {
             FrequencyType::LinearFrequencies => Self::new(VibratoTremolo::default(), 2.0 * 4.0),
             FrequencyType::AmigaFrequencies => Self::new(VibratoTremolo::default(), 2.0),
         }
+        .with_historical(historical)
+    }
+
+    /// Yxy, value is a panning offset
+    pub fn panbrello() -> Self {
+        Self::new(VibratoTremolo::default(), 0.5)
+    }
+
+    /// E4y, E7y: waveform selector bits, applied from the next tick
+    pub fn set_waveform(&mut self, waveform: u8) {
+        self.data.waveform = waveform & 7;
+    }
+
+    /// Seed of the random waveform
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.data.rng = Rng::new(seed);
+    }
+
+    /// The row doesn't use the effect anymore: the value is cleared, the position is kept
+    pub fn stop(&mut self) {
+        self.in_progress = false;
+        self.value = 0.0;
     }
 }
 
@@ -102,10 +142,12 @@ impl EffectPlugin for EffectVibratoTremolo {
         self.in_progress
     }
 
+    /// New note: the position restarts unless the waveform has the no retrigger bit
     fn retrigger(&mut self) -> f32 {
-        self.in_progress = false;
-        self.pos = 0.0;
-        self.value = 0.0;
+        self.stop();
+        if self.data.waveform & VibratoTremolo::WAVEFORM_NO_RETRIGGER == 0 {
+            self.pos = 0.0;
+        }
         self.value
     }
 
@@ -145,10 +187,88 @@ impl EffectXM2EffectPlugin for EffectVibratoTremolo {
                 }
             }
         } else {
-            let vol_data = param as f32 / 64.0;
-            if vol_data != 0.0 {
-                self.data.speed = vol_data;
+            // Volume column: 1 for Sx speed, 2 for Vx depth, in 4xy units
+            let vol_data = (param & 0x0F) as f32;
+            match volcolumn {
+                1 if vol_data != 0.0 => self.data.speed = vol_data / 64.0,
+                2 if vol_data != 0.0 => self.data.depth = vol_data / 16.0,
+                _ => {}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Values of `ticks` ticks, 8 ticks per period at depth 1.0
+    fn wave(effect: &mut EffectVibratoTremolo, ticks: usize) -> Vec<f32> {
+        (0..ticks).map(|_| effect.tick()).collect()
+    }
+
+    fn lfo(waveform: u8, historical: bool) -> EffectVibratoTremolo {
+        let mut effect = EffectVibratoTremolo::tremolo(historical);
+        effect.set_waveform(waveform);
+        effect.tick0(0.125, 1.0);
+        effect
+    }
+
+    #[test]
+    fn waveform_selector_bits() {
+        let sine = wave(&mut lfo(0, false), 8);
+        for (i, v) in sine.iter().enumerate() {
+            let expected = -(core::f32::consts::TAU * i as f32 / 8.0).sin();
+            assert!((v - expected).abs() < 1e-3, "{sine:?}");
+        }
+
+        // Ramp down: one jump up per period
+        let ramp = wave(&mut lfo(1, false), 16);
+        let jumps = ramp.windows(2).filter(|w| w[1] > w[0]).count();
+        assert_eq!(jumps, 2, "{ramp:?}");
+        assert!(ramp.iter().all(|v| (-1.0..=1.0).contains(v)));
+
+        let square = wave(&mut lfo(2, false), 8);
+        assert_eq!(square, [-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]);
+
+        // Random: anything between -1.0 and 1.0, FT2 plays a square
+        let random = wave(&mut lfo(3, false), 32);
+        assert!(random.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(random.iter().any(|&v| v != random[0]));
+        assert!(random.iter().any(|&v| v.abs() != 1.0));
+        assert_eq!(wave(&mut lfo(3, true), 8), square);
+
+        // Only the selector bits are kept
+        let mut effect = lfo(0xF2, false);
+        assert_eq!(effect.data.waveform, 2);
+        assert_eq!(wave(&mut effect, 8), square);
+    }
+
+    #[test]
+    fn new_note_retriggers_unless_bit_2() {
+        let mut effect = lfo(2, false);
+        wave(&mut effect, 5);
+        effect.retrigger();
+        assert_eq!(effect.value(), 0.0);
+        assert_eq!(wave(&mut effect, 2), [-1.0, -1.0]);
+
+        let mut effect = lfo(2 | VibratoTremolo::WAVEFORM_NO_RETRIGGER, false);
+        wave(&mut effect, 5);
+        effect.retrigger();
+        assert_eq!(wave(&mut effect, 2), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn random_waveform_follows_its_seed() {
+        let random = |seed: u32| {
+            let mut effect = lfo(3, false);
+            effect.set_random_seed(seed);
+            wave(&mut effect, 32)
+        };
+        assert_eq!(random(7), random(7));
+        assert_ne!(random(7), random(8));
+        // 0 can't seed xorshift, the default seed is used
+        assert_eq!(random(0), random(Rng::DEFAULT_SEED));
+    }
+}
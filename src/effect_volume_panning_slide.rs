@@ -1,8 +1,11 @@
 use crate::effect::*;
 use crate::helper::clamp;
 use core::default::Default;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EffectVolumePanningSlide {
     value: f32,
 }
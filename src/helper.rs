@@ -9,6 +9,34 @@ pub fn lerp(u: f32, v: f32, t: f32) -> f32 {
     t.mul_add(v - u, u)
 }
 
+/// Cubic Hermite (Catmull-Rom) between `x1` and `x2`
+#[inline(always)]
+pub fn hermite(x0: f32, x1: f32, x2: f32, x3: f32, t: f32) -> f32 {
+    let c1 = 0.5 * (x2 - x0);
+    let c2 = x0 - 2.5 * x1 + 2.0 * x2 - 0.5 * x3;
+    let c3 = 0.5 * (x3 - x0) + 1.5 * (x1 - x2);
+    ((c3 * t + c2) * t + c1) * t + x1
+}
+
+/// (left, right) volume with a constant power panning law, `panning` 0.0 is left and 1.0 is right
+#[inline(always)]
+pub fn pan_law(volume: f32, panning: f32) -> [f32; 2] {
+    [volume * (1.0 - panning).sqrt(), volume * panning.sqrt()]
+}
+
+/// Narrow or widen the stereo image of a (left, right) volume, `separation` 0.0 is mono and 1.0
+/// unchanged. A wider side stops at the mid: no side goes negative, out of phase.
+#[inline(always)]
+pub fn stereo_separation(volume: [f32; 2], separation: f32) -> [f32; 2] {
+    if separation == 1.0 {
+        return volume;
+    }
+    let [left, right] = volume;
+    let mid = (left + right) * 0.5;
+    let side = ((left - right) * 0.5 * separation).clamp(-mid, mid);
+    [mid + side, mid - side]
+}
+
 #[inline(always)]
 pub fn inverse_lerp(u: f32, v: f32, lerp: f32) -> f32 {
     (lerp - u) / (v - u)
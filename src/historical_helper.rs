@@ -2,10 +2,13 @@
 use micromath::F32Ext;
 #[cfg(feature = "libm")]
 use num_traits::float::Float;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Struct is very small we can clone it everywhere in other structs...
 
 #[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HistoricalHelper {
     pub tempo: u16,
 }
@@ -0,0 +1,117 @@
+/// Sample interpolation, see `XmrsPlayer::set_interpolation()`
+use crate::helper::*;
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How a sample is read between two of its points
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Interpolation {
+    /// No interpolation, the crunchy sound of Amiga and ST3
+    Nearest,
+    /// Linear interpolation, like FT2
+    #[default]
+    Linear,
+    /// Cubic Hermite (Catmull-Rom) on 4 points
+    Hermite,
+    /// Blackman windowed sinc on `taps` points, rounded up to an even number between 4 and 64
+    Sinc(usize),
+}
+
+/// Windowed sinc, tabulated from 0 to `half_width`
+#[derive(Clone, Debug)]
+pub(crate) struct SincKernel {
+    half_width: usize,
+    table: Vec<f32>,
+}
+
+impl SincKernel {
+    /// Table points between two sample points
+    const RESOLUTION: usize = 128;
+
+    pub(crate) fn new(half_width: usize) -> Self {
+        let len = half_width * Self::RESOLUTION + 1;
+        let mut table = vec![0.0; len + 1];
+        table[0] = 1.0;
+        for (i, value) in table.iter_mut().enumerate().take(len).skip(1) {
+            let x = i as f32 / Self::RESOLUTION as f32;
+            let sinc = (PI * x).sin() / (PI * x);
+            let w = PI * x / half_width as f32;
+            let blackman = 0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+            *value = sinc * blackman;
+        }
+        Self { half_width, table }
+    }
+
+    pub(crate) fn half_width(&self) -> usize {
+        self.half_width
+    }
+
+    /// Kernel value at `x` sample points from the center
+    #[inline(always)]
+    pub(crate) fn at(&self, x: f32) -> f32 {
+        let x = x.abs() * Self::RESOLUTION as f32;
+        let i = x as usize;
+        if i + 1 < self.table.len() {
+            lerp(self.table[i], self.table[i + 1], x - i as f32)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Interpolation mode and its precomputed data, shared by all channels
+#[derive(Clone, Debug)]
+pub(crate) struct Interpolator {
+    pub(crate) mode: Interpolation,
+    /// Step above which samples are low-pass filtered while read
+    pub(crate) band_limit: Option<f32>,
+    pub(crate) kernel: SincKernel,
+}
+
+impl Interpolator {
+    /// Kernel half width used for band limiting if the mode is not `Interpolation::Sinc`
+    const BAND_LIMIT_HALF_WIDTH: usize = 4;
+    /// Highest step followed by the filter, to keep the kernel length under control: faster
+    /// samples are filtered as if read with this step, and keep some aliasing
+    pub(crate) const MAX_BAND_LIMIT_STEP: f32 = 8.0;
+    /// Default band limiting threshold: above one sample point per frame, the sample holds
+    /// frequencies the output can't carry
+    pub(crate) const DEFAULT_BAND_LIMIT: f32 = 1.0;
+
+    pub(crate) fn new(mode: Interpolation, band_limit: Option<f32>) -> Self {
+        let half_width = match mode {
+            Interpolation::Sinc(taps) => taps.clamp(4, 64).div_ceil(2),
+            _ => Self::BAND_LIMIT_HALF_WIDTH,
+        };
+        Self {
+            mode,
+            band_limit: band_limit.map(|threshold| threshold.max(1.0)),
+            kernel: SincKernel::new(half_width),
+        }
+    }
+
+    /// Returns the low-pass stretch factor if a sample read with `step` must be band limited
+    #[inline(always)]
+    pub(crate) fn band_limit_scale(&self, step: f32) -> Option<f32> {
+        match self.band_limit {
+            Some(threshold) if step > threshold && self.mode != Interpolation::Nearest => {
+                Some(step.min(Self::MAX_BAND_LIMIT_STEP))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Interpolator {
+    fn default() -> Self {
+        Self::new(Interpolation::default(), Some(Self::DEFAULT_BAND_LIMIT))
+    }
+}
@@ -6,24 +6,41 @@ extern crate alloc;
 #[cfg(not(any(feature = "std", feature = "libm", feature = "micromath")))]
 ::core::compile_error!("Must enable at least one of features `std`, `libm`, or `micromath`");
 
+pub(crate) mod command_queue;
 pub(crate) mod effect;
 pub(crate) mod triggerkeep;
 
 pub(crate) mod effect_arpeggio;
+pub(crate) mod effect_invert_loop;
 pub(crate) mod effect_multi_retrig_note;
 pub(crate) mod effect_portamento;
+pub(crate) mod effect_s3m_memory;
 pub(crate) mod effect_toneportamento;
-//pub(crate) mod effect_tremor;
+pub(crate) mod effect_tremor;
 pub(crate) mod effect_vibrato_tremolo;
 pub(crate) mod effect_volume_panning_slide;
 
 pub mod channel;
+pub mod channel_info;
+pub mod channel_meter;
+pub mod compatibility;
 pub(crate) mod helper;
 pub(crate) mod historical_helper;
+pub mod interpolation;
+pub(crate) mod module_ref;
+pub mod paula;
+pub mod player_engine;
+pub mod player_observer;
+pub mod player_state;
 pub mod prelude;
+pub(crate) mod rng;
+pub(crate) mod sfx;
+pub mod song_timeline;
+pub mod subsong;
 pub(crate) mod state_auto_vibrato;
 pub(crate) mod state_envelope;
 pub(crate) mod state_instr_default;
 pub(crate) mod state_sample;
+pub(crate) mod state_volume_ramp;
 
 pub mod xmrsplayer;
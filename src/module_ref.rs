@@ -0,0 +1,22 @@
+/// A Module borrowed or shared by the player
+use alloc::sync::Arc;
+use core::ops::Deref;
+use xmrs::prelude::*;
+
+#[derive(Clone)]
+pub(crate) enum ModuleRef<'a> {
+    /// Borrowed by the caller, see `XmrsPlayer::new()`
+    Borrowed(&'a Module),
+    /// Owned by the player, or shared with other players, see `XmrsPlayer::from_arc()`
+    Shared(Arc<Module>),
+}
+
+impl<'a> Deref for ModuleRef<'a> {
+    type Target = Module;
+    fn deref(&self) -> &Module {
+        match self {
+            ModuleRef::Borrowed(module) => module,
+            ModuleRef::Shared(module) => module,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+/// Amiga Paula output stage, see `XmrsPlayer::set_paula_mode()`
+use core::f32::consts::PI;
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Low-pass filters of the emulated Amiga
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PaulaFilter {
+    /// No filter, hard panning only
+    None,
+    /// A500: 4.4 kHz RC low-pass, and the LED filter
+    A500,
+    /// A1200: 34 kHz RC low-pass, and the LED filter
+    A1200,
+}
+
+/// One-pole low-pass
+#[derive(Clone, Debug)]
+struct RcFilter {
+    a: f32,
+    state: [f32; 2],
+}
+
+impl RcFilter {
+    fn new(cutoff: f32, sample_rate: f32) -> Self {
+        Self {
+            a: 1.0 - (-2.0 * PI * cutoff / sample_rate).exp(),
+            state: [0.0; 2],
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, side: usize, input: f32) -> f32 {
+        self.state[side] += self.a * (input - self.state[side]);
+        self.state[side]
+    }
+}
+
+/// Two-pole low-pass (the Sallen-Key "LED" filter)
+#[derive(Clone, Debug)]
+struct LedFilter {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    /// (x1, x2, y1, y2) for each side
+    state: [[f32; 4]; 2],
+}
+
+impl LedFilter {
+    const CUTOFF: f32 = 3090.0;
+    const Q: f32 = 0.660;
+
+    fn new(sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * Self::CUTOFF.min(sample_rate * 0.45) / sample_rate;
+        let alpha = w0.sin() / (2.0 * Self::Q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 - cos) / 2.0 / a0,
+            b1: (1.0 - cos) / a0,
+            b2: (1.0 - cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            state: [[0.0; 4]; 2],
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, side: usize, input: f32) -> f32 {
+        let [x1, x2, y1, y2] = self.state[side];
+        let output = self.b0 * input + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+        self.state[side] = [input, x1, output, y1];
+        output
+    }
+}
+
+/// Filter memories and LED filter switch, part of snapshots
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct PaulaState {
+    rc: [f32; 2],
+    led: [[f32; 4]; 2],
+    led_on: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Paula {
+    filter: PaulaFilter,
+    rc: RcFilter,
+    led: LedFilter,
+    /// LED filter enabled by E0x
+    led_on: bool,
+}
+
+impl Paula {
+    pub(crate) fn new(filter: PaulaFilter, sample_rate: f32) -> Self {
+        let cutoff = match filter {
+            PaulaFilter::A1200 => 34419.0,
+            _ => 4421.0,
+        };
+        Self {
+            filter,
+            rc: RcFilter::new(cutoff, sample_rate),
+            led: LedFilter::new(sample_rate),
+            led_on: false,
+        }
+    }
+
+    pub(crate) fn filter(&self) -> PaulaFilter {
+        self.filter
+    }
+
+    /// Channels 0 and 3 are on the left side, 1 and 2 on the right side
+    pub(crate) fn side(channel: usize) -> usize {
+        match channel % 4 {
+            0 | 3 => 0,
+            _ => 1,
+        }
+    }
+
+    pub(crate) fn is_led_on(&self) -> bool {
+        self.led_on
+    }
+
+    /// E0x: 0 enables the LED filter, 1 disables it
+    pub(crate) fn set_led(&mut self, on: bool) {
+        self.led_on = on;
+    }
+
+    /// Clear filter memories and switch the LED filter off
+    pub(crate) fn reset(&mut self) {
+        self.set_state(&PaulaState::default());
+    }
+
+    pub(crate) fn state(&self) -> PaulaState {
+        PaulaState {
+            rc: self.rc.state,
+            led: self.led.state,
+            led_on: self.led_on,
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, state: &PaulaState) {
+        self.rc.state = state.rc;
+        self.led.state = state.led;
+        self.led_on = state.led_on;
+    }
+
+    #[inline(always)]
+    pub(crate) fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        if self.filter == PaulaFilter::None {
+            return (left, right);
+        }
+        let mut frame = [left, right];
+        for (side, value) in frame.iter_mut().enumerate() {
+            *value = self.rc.process(side, *value);
+            if self.led_on {
+                *value = self.led.process(side, *value);
+            }
+        }
+        (frame[0], frame[1])
+    }
+}
@@ -0,0 +1,458 @@
+/// Real-time engine and its lock-free controller, see `XmrsPlayer::split()`
+use crate::command_queue::{CommandQueue, Message};
+use crate::xmrsplayer::XmrsPlayer;
+use alloc::sync::Arc;
+use core::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+use xmrs::prelude::*;
+
+/// A command sent by a `PlayerController`, applied by the `PlayerEngine` when the next buffer is
+/// rendered, or at the next tick boundary while a buffer is rendered. Commands are still applied
+/// when the song is paused or has ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerCommand {
+    /// Same as `XmrsPlayer::goto()`
+    Goto {
+        table_position: usize,
+        row: usize,
+        speed: u16,
+    },
+    /// Same as `XmrsPlayer::pause()`
+    Pause(bool),
+    /// Same as `XmrsPlayer::set_mute_channel()`
+    MuteChannel { channel: usize, mute: bool },
+    /// Same as `XmrsPlayer::mute_all()`
+    MuteAll(bool),
+    /// Set `XmrsPlayer::amplification`
+    Amplification(f32),
+    /// Same as `XmrsPlayer::set_max_loop_count()`
+    MaxLoopCount(usize),
+    /// Same as `XmrsPlayer::set_channel_volume()`
+    ChannelVolume { channel: usize, volume: f32 },
+    /// Same as `XmrsPlayer::set_channel_pan_override()`
+    ChannelPanOverride {
+        channel: usize,
+        panning: Option<f32>,
+    },
+    /// Same as `XmrsPlayer::solo_channel()`
+    SoloChannel { channel: usize, exclusive: bool },
+    /// Same as `XmrsPlayer::unsolo_channel()`
+    UnsoloChannel(usize),
+    /// Same as `XmrsPlayer::clear_solo()`
+    ClearSolo,
+    /// Same as `XmrsPlayer::note_on()`, the volume is rounded to 1/255
+    NoteOn {
+        channel: usize,
+        instrument: usize,
+        note: Note,
+        volume: f32,
+    },
+    /// Same as `XmrsPlayer::note_off()`
+    NoteOff(usize),
+}
+
+impl PlayerCommand {
+    /// A NaN, used for `ChannelPanOverride { panning: None, .. }`
+    const NO_PANNING: u32 = u32::MAX;
+
+    fn encode(&self) -> Message {
+        let word = |value: usize| value.min(u32::MAX as usize) as u32;
+        match *self {
+            PlayerCommand::Goto {
+                table_position,
+                row,
+                speed,
+            } => [
+                0,
+                word(table_position),
+                (row.min(u16::MAX as usize) as u32) << 16 | speed as u32,
+            ],
+            PlayerCommand::Pause(pause) => [1, pause as u32, 0],
+            PlayerCommand::MuteChannel { channel, mute } => [2, word(channel), mute as u32],
+            PlayerCommand::MuteAll(mute) => [3, mute as u32, 0],
+            PlayerCommand::Amplification(amplification) => [4, amplification.to_bits(), 0],
+            PlayerCommand::MaxLoopCount(count) => [5, word(count), 0],
+            PlayerCommand::ChannelVolume { channel, volume } => {
+                [6, word(channel), volume.to_bits()]
+            }
+            PlayerCommand::ChannelPanOverride { channel, panning } => [
+                7,
+                word(channel),
+                panning.map_or(Self::NO_PANNING, f32::to_bits),
+            ],
+            PlayerCommand::SoloChannel { channel, exclusive } => {
+                [8, word(channel), exclusive as u32]
+            }
+            PlayerCommand::UnsoloChannel(channel) => [9, word(channel), 0],
+            PlayerCommand::ClearSolo => [10, 0, 0],
+            PlayerCommand::NoteOn {
+                channel,
+                instrument,
+                note,
+                volume,
+            } => [
+                11,
+                word(channel),
+                (instrument.min(u16::MAX as usize) as u32) << 16
+                    | (note.value() as u32) << 8
+                    | (volume.clamp(0.0, 1.0) * 255.0 + 0.5) as u32,
+            ],
+            PlayerCommand::NoteOff(channel) => [12, word(channel), 0],
+        }
+    }
+
+    fn decode(message: Message) -> Option<Self> {
+        let [kind, a, b] = message;
+        match kind {
+            0 => Some(PlayerCommand::Goto {
+                table_position: a as usize,
+                row: (b >> 16) as usize,
+                speed: b as u16,
+            }),
+            1 => Some(PlayerCommand::Pause(a != 0)),
+            2 => Some(PlayerCommand::MuteChannel {
+                channel: a as usize,
+                mute: b != 0,
+            }),
+            3 => Some(PlayerCommand::MuteAll(a != 0)),
+            4 => Some(PlayerCommand::Amplification(f32::from_bits(a))),
+            5 => Some(PlayerCommand::MaxLoopCount(a as usize)),
+            6 => Some(PlayerCommand::ChannelVolume {
+                channel: a as usize,
+                volume: f32::from_bits(b),
+            }),
+            7 => Some(PlayerCommand::ChannelPanOverride {
+                channel: a as usize,
+                panning: (b != Self::NO_PANNING).then(|| f32::from_bits(b)),
+            }),
+            8 => Some(PlayerCommand::SoloChannel {
+                channel: a as usize,
+                exclusive: b != 0,
+            }),
+            9 => Some(PlayerCommand::UnsoloChannel(a as usize)),
+            10 => Some(PlayerCommand::ClearSolo),
+            11 => Some(PlayerCommand::NoteOn {
+                channel: a as usize,
+                instrument: (b >> 16) as usize,
+                note: Note::try_from((b >> 8) as u8).unwrap_or_default(),
+                volume: (b & 0xFF) as f32 / 255.0,
+            }),
+            12 => Some(PlayerCommand::NoteOff(a as usize)),
+            _ => None,
+        }
+    }
+}
+
+/// Player position, published by the engine after each rendered buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerStatus {
+    /// Current index in pattern_order
+    pub table_index: usize,
+    /// Current pattern number
+    pub pattern: usize,
+    /// Current row
+    pub row: usize,
+    /// How many times the song has looped
+    pub loop_count: usize,
+    /// Same as `XmrsPlayer::generated_samples`
+    pub generated_samples: u64,
+    pub paused: bool,
+    /// True if max loop count is reached
+    pub ended: bool,
+}
+
+/// Status written by the engine only, readers retry if a write was in progress (seqlock)
+struct StatusCell {
+    version: AtomicUsize,
+    table_index: AtomicUsize,
+    pattern: AtomicUsize,
+    row: AtomicUsize,
+    loop_count: AtomicUsize,
+    generated_samples: [AtomicU32; 2],
+    flags: AtomicU32,
+}
+
+impl StatusCell {
+    const PAUSED: u32 = 0b01;
+    const ENDED: u32 = 0b10;
+
+    fn new() -> Self {
+        Self {
+            version: AtomicUsize::new(0),
+            table_index: AtomicUsize::new(0),
+            pattern: AtomicUsize::new(0),
+            row: AtomicUsize::new(0),
+            loop_count: AtomicUsize::new(0),
+            generated_samples: [AtomicU32::new(0), AtomicU32::new(0)],
+            flags: AtomicU32::new(0),
+        }
+    }
+
+    fn store(&self, status: &PlayerStatus) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version
+            .store(version.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.table_index
+            .store(status.table_index, Ordering::Relaxed);
+        self.pattern.store(status.pattern, Ordering::Relaxed);
+        self.row.store(status.row, Ordering::Relaxed);
+        self.loop_count.store(status.loop_count, Ordering::Relaxed);
+        let [low, high] = &self.generated_samples;
+        low.store(status.generated_samples as u32, Ordering::Relaxed);
+        high.store((status.generated_samples >> 32) as u32, Ordering::Relaxed);
+        let mut flags = 0;
+        if status.paused {
+            flags |= Self::PAUSED;
+        }
+        if status.ended {
+            flags |= Self::ENDED;
+        }
+        self.flags.store(flags, Ordering::Relaxed);
+        self.version
+            .store(version.wrapping_add(2), Ordering::Release);
+    }
+
+    fn load(&self) -> PlayerStatus {
+        loop {
+            let version = self.version.load(Ordering::Acquire);
+            if version & 1 == 1 {
+                core::hint::spin_loop();
+                continue;
+            }
+            let flags = self.flags.load(Ordering::Relaxed);
+            let low = self.generated_samples[0].load(Ordering::Relaxed) as u64;
+            let high = self.generated_samples[1].load(Ordering::Relaxed) as u64;
+            let status = PlayerStatus {
+                table_index: self.table_index.load(Ordering::Relaxed),
+                pattern: self.pattern.load(Ordering::Relaxed),
+                row: self.row.load(Ordering::Relaxed),
+                loop_count: self.loop_count.load(Ordering::Relaxed),
+                generated_samples: high << 32 | low,
+                paused: flags & Self::PAUSED != 0,
+                ended: flags & Self::ENDED != 0,
+            };
+            fence(Ordering::Acquire);
+            if self.version.load(Ordering::Relaxed) == version {
+                return status;
+            }
+        }
+    }
+}
+
+struct Shared {
+    commands: CommandQueue,
+    status: StatusCell,
+}
+
+/// Cloneable handle to drive a `PlayerEngine` from any thread, without any lock.
+#[derive(Clone)]
+pub struct PlayerController {
+    shared: Arc<Shared>,
+}
+
+impl PlayerController {
+    /// Send a command to the engine. Returns false if the queue is full, the command is then dropped.
+    pub fn send(&self, command: PlayerCommand) -> bool {
+        self.shared.commands.push(command.encode())
+    }
+
+    pub fn goto(&self, table_position: usize, row: usize, speed: u16) -> bool {
+        self.send(PlayerCommand::Goto {
+            table_position,
+            row,
+            speed,
+        })
+    }
+
+    pub fn pause(&self, pause: bool) -> bool {
+        self.send(PlayerCommand::Pause(pause))
+    }
+
+    pub fn set_mute_channel(&self, channel: usize, mute: bool) -> bool {
+        self.send(PlayerCommand::MuteChannel { channel, mute })
+    }
+
+    pub fn mute_all(&self, mute: bool) -> bool {
+        self.send(PlayerCommand::MuteAll(mute))
+    }
+
+    pub fn set_amplification(&self, amplification: f32) -> bool {
+        self.send(PlayerCommand::Amplification(amplification))
+    }
+
+    pub fn set_max_loop_count(&self, max_loop_count: usize) -> bool {
+        self.send(PlayerCommand::MaxLoopCount(max_loop_count))
+    }
+
+    pub fn set_channel_volume(&self, channel: usize, volume: f32) -> bool {
+        self.send(PlayerCommand::ChannelVolume { channel, volume })
+    }
+
+    pub fn set_channel_pan_override(&self, channel: usize, panning: Option<f32>) -> bool {
+        self.send(PlayerCommand::ChannelPanOverride { channel, panning })
+    }
+
+    pub fn solo_channel(&self, channel: usize, exclusive: bool) -> bool {
+        self.send(PlayerCommand::SoloChannel { channel, exclusive })
+    }
+
+    pub fn unsolo_channel(&self, channel: usize) -> bool {
+        self.send(PlayerCommand::UnsoloChannel(channel))
+    }
+
+    pub fn clear_solo(&self) -> bool {
+        self.send(PlayerCommand::ClearSolo)
+    }
+
+    pub fn note_on(&self, channel: usize, instrument: usize, note: Note, volume: f32) -> bool {
+        self.send(PlayerCommand::NoteOn {
+            channel,
+            instrument,
+            note,
+            volume,
+        })
+    }
+
+    pub fn note_off(&self, channel: usize) -> bool {
+        self.send(PlayerCommand::NoteOff(channel))
+    }
+
+    /// Last status published by the engine
+    pub fn status(&self) -> PlayerStatus {
+        self.shared.status.load()
+    }
+
+    /// Maximum number of pending commands
+    pub fn capacity(&self) -> usize {
+        self.shared.commands.capacity()
+    }
+}
+
+/// Real-time side of a split player: it never blocks nor allocates while rendering.
+pub struct PlayerEngine<'a> {
+    player: XmrsPlayer<'a>,
+    shared: Arc<Shared>,
+}
+
+impl<'a> PlayerEngine<'a> {
+    /// `queue_capacity` is rounded up to a power of two
+    pub(crate) fn new(player: XmrsPlayer<'a>, queue_capacity: usize) -> (Self, PlayerController) {
+        let shared = Arc::new(Shared {
+            commands: CommandQueue::new(queue_capacity),
+            status: StatusCell::new(),
+        });
+        let engine = Self {
+            player,
+            shared: Arc::clone(&shared),
+        };
+        engine.publish_status();
+        (engine, PlayerController { shared })
+    }
+
+    /// Returns a new controller for this engine
+    pub fn controller(&self) -> PlayerController {
+        PlayerController {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    pub fn player(&self) -> &XmrsPlayer<'a> {
+        &self.player
+    }
+
+    /// Direct access to the player, when the engine is not used by another thread
+    pub fn player_mut(&mut self) -> &mut XmrsPlayer<'a> {
+        &mut self.player
+    }
+
+    pub fn into_player(self) -> XmrsPlayer<'a> {
+        self.player
+    }
+
+    fn apply_commands(player: &mut XmrsPlayer<'a>, commands: &CommandQueue) {
+        while let Some(message) = commands.pop() {
+            match PlayerCommand::decode(message) {
+                Some(PlayerCommand::Goto {
+                    table_position,
+                    row,
+                    speed,
+                }) => {
+                    player.goto(table_position, row, speed);
+                }
+                Some(PlayerCommand::Pause(pause)) => player.pause(pause),
+                Some(PlayerCommand::MuteChannel { channel, mute }) => {
+                    player.set_mute_channel(channel, mute)
+                }
+                Some(PlayerCommand::MuteAll(mute)) => player.mute_all(mute),
+                Some(PlayerCommand::Amplification(amplification)) => {
+                    player.amplification = amplification
+                }
+                Some(PlayerCommand::MaxLoopCount(count)) => player.set_max_loop_count(count),
+                Some(PlayerCommand::ChannelVolume { channel, volume }) => {
+                    player.set_channel_volume(channel, volume)
+                }
+                Some(PlayerCommand::ChannelPanOverride { channel, panning }) => {
+                    player.set_channel_pan_override(channel, panning)
+                }
+                Some(PlayerCommand::SoloChannel { channel, exclusive }) => {
+                    player.solo_channel(channel, exclusive)
+                }
+                Some(PlayerCommand::UnsoloChannel(channel)) => player.unsolo_channel(channel),
+                Some(PlayerCommand::ClearSolo) => player.clear_solo(),
+                Some(PlayerCommand::NoteOn {
+                    channel,
+                    instrument,
+                    note,
+                    volume,
+                }) => {
+                    player.note_on(channel, instrument, note, volume);
+                }
+                Some(PlayerCommand::NoteOff(channel)) => player.note_off(channel),
+                None => {}
+            }
+        }
+    }
+
+    fn publish_status(&self) {
+        let player = &self.player;
+        let table_index = player.get_current_table_index();
+        self.shared.status.store(&PlayerStatus {
+            table_index,
+            pattern: player
+                .module()
+                .pattern_order
+                .get(table_index)
+                .copied()
+                .unwrap_or(0),
+            row: player.get_current_row(),
+            loop_count: player.get_loop_count(),
+            generated_samples: player.generated_samples,
+            paused: player.is_paused(),
+            ended: player.is_song_ended(),
+        });
+    }
+
+    /// Same as `XmrsPlayer::render_interleaved()`, pending commands are applied first, then at each
+    /// tick boundary.
+    pub fn render_interleaved(&mut self, buffer: &mut [f32]) -> usize {
+        let commands = &self.shared.commands;
+        Self::apply_commands(&mut self.player, commands);
+        let frames = self
+            .player
+            .render_interleaved_with(buffer, |player| Self::apply_commands(player, commands));
+        self.publish_status();
+        frames
+    }
+
+    /// Same as `XmrsPlayer::render_planar()`, pending commands are applied first, then at each
+    /// tick boundary.
+    pub fn render_planar(&mut self, left: &mut [f32], right: &mut [f32]) -> usize {
+        let commands = &self.shared.commands;
+        Self::apply_commands(&mut self.player, commands);
+        let frames = self
+            .player
+            .render_planar_with(left, right, |player| Self::apply_commands(player, commands));
+        self.publish_status();
+        frames
+    }
+}
@@ -0,0 +1,41 @@
+/// Playback events, see `XmrsPlayer::set_observer()`
+use xmrs::prelude::*;
+
+/// Receive playback events from a `XmrsPlayer`.
+///
+/// `frame` is the frame (a (left,right) sample, see `XmrsPlayer::generated_samples`) where the
+/// event takes effect: events are sent while rendering, so `frame` can be ahead of what is heard.
+/// Every method does nothing by default.
+pub trait PlayerObserver {
+    /// A row starts, at index `order` in pattern_order
+    fn on_row(&mut self, _frame: u64, _order: usize, _pattern: usize, _row: usize) {}
+
+    /// A tick starts, `tick` is 0 on the first tick of a row
+    fn on_tick(&mut self, _frame: u64, _tick: u16) {}
+
+    /// A note is triggered, or triggered again by E9x, Rxy or a delayed key off next to an
+    /// instrument. `instrument` is an index in the module and `volume` is between 0.0 and 1.0
+    fn on_note_on(
+        &mut self,
+        _frame: u64,
+        _channel: usize,
+        _instrument: usize,
+        _note: Note,
+        _volume: f32,
+    ) {
+    }
+
+    /// A note is released (note off, with or without an instrument, or Kxx) or cut by ECx
+    fn on_note_off(&mut self, _frame: u64, _channel: usize) {}
+
+    /// A sync marker is found at the start of a row: an effect without meaning for the player,
+    /// E8x, or the effect selected with `XmrsPlayer::set_sync_effect()`. `effect_type` uses XM
+    /// numbering.
+    fn on_sync(&mut self, _frame: u64, _channel: usize, _effect_type: u8, _param: u8) {}
+
+    /// The song loops back, `loop_count` is the number of loops done
+    fn on_loop(&mut self, _frame: u64, _loop_count: usize) {}
+
+    /// Max loop count is reached, no more frames will be rendered
+    fn on_song_end(&mut self, _frame: u64) {}
+}
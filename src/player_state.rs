@@ -0,0 +1,161 @@
+/// A complete snapshot of the player
+use crate::channel::Channel;
+use crate::historical_helper::HistoricalHelper;
+use crate::paula::PaulaState;
+use crate::sfx::SfxVoice;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use xmrs::prelude::*;
+
+/// Why `XmrsPlayer::restore()` refused a snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The snapshot was saved by another version of the player, with another format
+    Version,
+    /// The snapshot was taken at another sample rate
+    SampleRate,
+    /// The snapshot was taken with another historical mode
+    Historical,
+    /// The snapshot was taken with another module: number of channels or song length
+    Module,
+    /// Position in the song (table index, row, jump destination or jump row) out of the module
+    Position,
+    /// Instrument, sample or sample position of a channel out of the module
+    Channel(usize),
+    /// Not the same number of sound effect voices, or a voice out of the module or of the
+    /// sound effect samples
+    Sfx,
+}
+
+/// Opaque snapshot of a `XmrsPlayer`, see `XmrsPlayer::snapshot()` and `XmrsPlayer::restore()`.
+///
+/// It contains everything needed to replay the song bit-identically from the snapshot point:
+/// channels, instruments, samples and envelopes states, effect memories, loop counters, tick accumulator,
+/// Amiga filter memories and sound effect voices.
+///
+/// Instruments and samples are stored as indices in the module, not as references: with the `serde`
+/// feature, a snapshot can be saved and restored later in another player using the same module.
+/// A saved snapshot carries its format version: a snapshot saved by a player with another format
+/// is refused by `XmrsPlayer::restore()`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlayerState {
+    pub(crate) version: u32,
+    pub(crate) sample_rate: f32,
+
+    pub(crate) tempo: u16,
+    pub(crate) bpm: u16,
+    pub(crate) global_volume: f32,
+    pub(crate) global_volume_slide_param: u8,
+    pub(crate) current_table_index: usize,
+    pub(crate) current_row: usize,
+    pub(crate) current_tick: u16,
+    pub(crate) remaining_samples_in_tick: f32,
+    pub(crate) generated_samples: u64,
+
+    pub(crate) position_jump: bool,
+    pub(crate) pattern_break: bool,
+    pub(crate) jump_dest: usize,
+    pub(crate) jump_row: usize,
+    pub(crate) extra_ticks: u16,
+
+    pub(crate) channel: Vec<Channel>,
+
+    pub(crate) row_loop_count: Vec<Vec<usize>>,
+    pub(crate) loop_count: usize,
+    pub(crate) start_table_index: usize,
+    pub(crate) song_end_sent: bool,
+
+    pub(crate) right_sample: Option<f32>,
+    pub(crate) hhelper: Option<HistoricalHelper>,
+    pub(crate) paula: Option<PaulaState>,
+
+    pub(crate) sfx: Vec<SfxVoice>,
+    pub(crate) sfx_count: u64,
+    pub(crate) sfx_remaining_in_tick: f32,
+    pub(crate) ducking: bool,
+}
+
+impl PlayerState {
+    /// Format version of the snapshots taken by this player, to be increased each time the
+    /// snapshot content changes
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Returns the format version of this snapshot
+    pub fn get_format_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the frame (a (left,right) sample) where the snapshot was taken
+    pub fn get_generated_samples(&self) -> u64 {
+        self.generated_samples
+    }
+
+    /// Returns index in pattern_order where the snapshot was taken
+    pub fn get_current_table_index(&self) -> usize {
+        self.current_table_index
+    }
+
+    /// Returns the next row to be played when the snapshot was taken
+    pub fn get_current_row(&self) -> usize {
+        self.current_row
+    }
+
+    /// Checks that this snapshot can be restored in a player using `module` at `sample_rate`,
+    /// without any index out of the module
+    pub(crate) fn check(
+        &self,
+        module: &Module,
+        sample_rate: f32,
+        historical: bool,
+        sfx_samples: &[Sample],
+        sfx_voices: usize,
+    ) -> Result<(), RestoreError> {
+        if self.version != Self::FORMAT_VERSION {
+            return Err(RestoreError::Version);
+        }
+        if self.sample_rate != sample_rate {
+            return Err(RestoreError::SampleRate);
+        }
+        if self.hhelper.is_some() != historical {
+            return Err(RestoreError::Historical);
+        }
+        let song_length = module.get_song_length();
+        if self.channel.len() != module.get_num_channels()
+            || self.row_loop_count.len() != song_length
+            || self.row_loop_count.iter().any(|r| r.len() != MAX_NUM_ROWS)
+        {
+            return Err(RestoreError::Module);
+        }
+
+        let num_rows = |table_index: usize| match module.pattern_order.get(table_index) {
+            Some(&p) if p < module.pattern.len() => Some(module.get_num_rows(p)),
+            _ => None,
+        };
+        let jumping = self.position_jump || self.pattern_break;
+        let position_ok = num_rows(self.start_table_index).is_some()
+            && num_rows(self.current_table_index)
+                .is_some_and(|rows| jumping || self.current_row < rows)
+            && self.jump_dest < song_length
+            && self.jump_row < MAX_NUM_ROWS
+            && self.current_row < MAX_NUM_ROWS;
+        if !position_ok {
+            return Err(RestoreError::Position);
+        }
+
+        if let Some(i) = self.channel.iter().position(|ch| !ch.is_valid_for(module)) {
+            return Err(RestoreError::Channel(i));
+        }
+
+        if self.sfx.len() != sfx_voices
+            || self
+                .sfx
+                .iter()
+                .any(|v| !v.is_valid_for(module, sfx_samples))
+        {
+            return Err(RestoreError::Sfx);
+        }
+        Ok(())
+    }
+}
@@ -5,4 +5,14 @@
 /// use xmrsplayer::prelude::*;
 /// ```
 ///
+pub use crate::channel_info::{ActiveEffects, ChannelInfo, LoopDirection};
+pub use crate::channel_meter::ChannelMeter;
+pub use crate::compatibility::Compatibility;
+pub use crate::interpolation::Interpolation;
+pub use crate::paula::PaulaFilter;
+pub use crate::player_engine::{PlayerCommand, PlayerController, PlayerEngine, PlayerStatus};
+pub use crate::player_observer::PlayerObserver;
+pub use crate::player_state::{PlayerState, RestoreError};
+pub use crate::song_timeline::{LoopPoint, SongTimeline};
+pub use crate::subsong::Subsong;
 pub use crate::xmrsplayer::XmrsPlayer;
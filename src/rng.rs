@@ -0,0 +1,50 @@
+/// Deterministic pseudo-random numbers, see `XmrsPlayer::set_random_seed()`
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Xorshift32 generator, small enough to live in every effect state
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub(crate) const DEFAULT_SEED: u32 = 0x2545_F491;
+
+    /// 0 is replaced by `DEFAULT_SEED`, xorshift never leaves 0
+    pub(crate) fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { Self::DEFAULT_SEED } else { seed },
+        }
+    }
+
+    /// Derive an independent seed for `stream` (a channel, an effect...) from `seed`
+    pub(crate) fn derive(seed: u32, stream: u32) -> u32 {
+        let mut x = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x85EB_CA6B);
+        x ^= x >> 13;
+        x
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniform value between -1.0 and 1.0
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED)
+    }
+}
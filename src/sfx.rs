@@ -0,0 +1,236 @@
+/// Sound effect voices mixed over the music, see `XmrsPlayer::set_sfx_voices()`
+use crate::channel::Channel;
+use crate::helper::*;
+use crate::historical_helper::HistoricalHelper;
+use crate::interpolation::Interpolator;
+use crate::state_sample::StateSample;
+use crate::state_volume_ramp::StateVolumeRamp;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use xmrs::prelude::*;
+
+/// A raw sample played by a sound effect voice
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct RawVoice {
+    /// Index in the player sound effect samples
+    sample: usize,
+    state: StateSample,
+    volume: f32,
+    /// 0.0 is left, 1.0 is right
+    panning: f32,
+    /// (left, right) volume really applied
+    ramp: StateVolumeRamp,
+    /// Fading out, stopped once the ramp is done
+    released: bool,
+}
+
+impl RawVoice {
+    fn is_playing(&self) -> bool {
+        self.state.is_enabled() && !(self.released && self.ramp.is_done())
+    }
+}
+
+/// A voice that the patterns never touch
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct SfxVoice {
+    /// Plays module instruments
+    channel: Channel,
+    /// Played instead of the channel if set
+    raw: Option<RawVoice>,
+    priority: u8,
+    /// Start order, the oldest voice is stolen first
+    started: u64,
+    /// Mixer settings of the raw samples, like the channel ones
+    volume_ramp: usize,
+    stereo_separation: f32,
+}
+
+impl SfxVoice {
+    pub(crate) fn new(module: &Module, rate: f32, historical: Option<HistoricalHelper>) -> Self {
+        Self {
+            channel: Channel::new(module, rate, historical),
+            raw: None,
+            priority: 0,
+            started: 0,
+            volume_ramp: 0,
+            stereo_separation: 1.0,
+        }
+    }
+
+    /// Same settings as the music channels, see `Channel::set_volume_ramp()`
+    pub(crate) fn set_mixer(&mut self, volume_ramp: usize, note_fadeout: bool, separation: f32) {
+        self.channel.set_volume_ramp(volume_ramp, note_fadeout);
+        self.channel.set_stereo_separation(separation);
+        self.volume_ramp = volume_ramp;
+        if separation != self.stereo_separation {
+            self.stereo_separation = separation;
+            if let Some(raw) = self.raw.as_mut().filter(|raw| !raw.released) {
+                let target = stereo_separation(pan_law(raw.volume, raw.panning), separation);
+                raw.ramp.set_target(target, volume_ramp);
+            }
+        }
+    }
+
+    pub(crate) fn channel_mut(&mut self) -> &mut Channel {
+        &mut self.channel
+    }
+
+    pub(crate) fn is_playing(&self) -> bool {
+        match &self.raw {
+            Some(raw) => raw.is_playing(),
+            None => self.channel.playing_note().is_some() || self.channel.is_fading_out(),
+        }
+    }
+
+    /// Returns true if every index still points to `module` data or to one of `samples`
+    pub(crate) fn is_valid_for(&self, module: &Module, samples: &[Sample]) -> bool {
+        self.channel.is_valid_for(module)
+            && self.raw.as_ref().is_none_or(|raw| {
+                samples
+                    .get(raw.sample)
+                    .is_some_and(|sample| raw.state.is_valid_for(sample))
+            })
+    }
+
+    /// Returns true if a new sound with `priority` can replace this one
+    pub(crate) fn can_be_stolen(&self, priority: u8) -> bool {
+        !self.is_playing() || self.priority <= priority
+    }
+
+    /// Steal order: idle voices, then lowest priority, then oldest
+    pub(crate) fn steal_order(&self) -> (bool, u8, u64) {
+        (self.is_playing(), self.priority, self.started)
+    }
+
+    /// Play a module instrument, returns false if nothing can be played
+    pub(crate) fn play_instrument(
+        &mut self,
+        module: &Module,
+        instrument: usize,
+        note: Note,
+        volume: f32,
+        priority: u8,
+        started: u64,
+    ) -> bool {
+        self.stop();
+        self.priority = priority;
+        self.started = started;
+        self.channel.note_on(module, instrument, note, volume)
+    }
+
+    /// Play a raw sample at `frequency` Hz, panning is 0.0 (left) to 1.0 (right)
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn play_sample(
+        &mut self,
+        sample: &Sample,
+        num: usize,
+        frequency: f32,
+        volume: f32,
+        panning: f32,
+        priority: u8,
+        started: u64,
+        rate: f32,
+    ) -> bool {
+        self.stop();
+        self.priority = priority;
+        self.started = started;
+        let mut state = StateSample::new(sample, 0, num, rate);
+        state.set_step(frequency);
+        let volume = volume.clamp(0.0, 1.0) * sample.volume;
+        let panning = panning.clamp(0.0, 1.0);
+        // From silence, like a new note on a channel
+        let mut ramp = StateVolumeRamp::default();
+        let target = stereo_separation(pan_law(volume, panning), self.stereo_separation);
+        ramp.set_target(target, self.volume_ramp);
+        self.raw = Some(RawVoice {
+            sample: num,
+            state,
+            volume,
+            panning,
+            ramp,
+            released: false,
+        });
+        self.is_playing()
+    }
+
+    /// Release the note, raw samples fade out over the volume ramp
+    pub(crate) fn release(&mut self, module: &Module) {
+        match &mut self.raw {
+            Some(_) if self.volume_ramp == 0 => self.raw = None,
+            Some(raw) => {
+                raw.released = true;
+                raw.ramp.set_target([0.0, 0.0], self.volume_ramp);
+            }
+            None => self.channel.note_off(module),
+        }
+    }
+
+    /// Stop immediately
+    pub(crate) fn stop(&mut self) {
+        self.raw = None;
+        self.channel.stop();
+    }
+
+    pub(crate) fn tick(&mut self, module: &Module, current_tick: u16) {
+        if self.raw.is_none() {
+            self.channel.tick(module, current_tick);
+        }
+    }
+
+    /// Mix `frames` frames, `add` receives (frame, left, right)
+    pub(crate) fn mix<F: FnMut(usize, f32, f32)>(
+        &mut self,
+        module: &Module,
+        samples: &[Sample],
+        interpolator: &Interpolator,
+        frames: usize,
+        mut add: F,
+    ) {
+        if let Some(raw) = &mut self.raw {
+            let sample = match samples.get(raw.sample) {
+                Some(s) => s,
+                None => return,
+            };
+            for frame in 0..frames {
+                if !raw.is_playing() {
+                    break;
+                }
+                match raw.state.next(sample, interpolator) {
+                    Some(value) => {
+                        let [left, right] = raw.ramp.next();
+                        add(frame, value * left, value * right)
+                    }
+                    None => break,
+                }
+            }
+            if !raw.is_playing() {
+                self.raw = None;
+            }
+            return;
+        }
+        let ch = &mut self.channel;
+        let mut fadeout = ch.fadeout_sample_ref(module);
+        let mut sample = ch.sample_ref(module);
+        for frame in 0..frames {
+            if sample.is_none() && fadeout.is_none() {
+                break;
+            }
+            let (mut left, mut right) = (0.0, 0.0);
+            if let Some(s) = fadeout {
+                match ch.next_fadeout_frame(s, interpolator) {
+                    Some((l, r)) => (left, right) = (left + l, right + r),
+                    None => fadeout = None,
+                }
+            }
+            if let Some(s) = sample {
+                match ch.next_frame(s, interpolator) {
+                    Some((l, r)) => (left, right) = (left + l, right + r),
+                    None => sample = None,
+                }
+            }
+            add(frame, left, right);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+/// Song length and timeline analysis, without audio rendering
+use crate::xmrsplayer::XmrsPlayer;
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+use xmrs::prelude::*;
+
+/// Where the song loops back
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopPoint {
+    /// Index in pattern_order where the song restarts
+    pub table_index: usize,
+    /// Row where the song restarts
+    pub row: usize,
+    /// Frame where playback jumps back
+    pub frame: u64,
+    /// Frame where the restarted row was first played
+    pub target_frame: u64,
+}
+
+/// Timeline of a song, computed by walking the module through the player state machine
+/// (Bxx, Dxx, E6y, EEy, Fxx...) without mixing any sample.
+#[derive(Clone, Debug)]
+pub struct SongTimeline {
+    sample_rate: f32,
+    total_frames: u64,
+    complete: bool,
+    /// First start frame of each (table index, row)
+    row_start: Vec<Vec<Option<u64>>>,
+    loop_point: Option<LoopPoint>,
+}
+
+impl SongTimeline {
+    /// Safety limit for songs looping forever (FT2 E60 bug...): rows played per song row and per loop
+    const MAX_PASSES_PER_ROW: usize = 256;
+
+    /// Analyse a song from its beginning.
+    ///
+    /// `max_loop_count` is the same as `XmrsPlayer::set_max_loop_count()`, 0 is understood as 1 (play once).
+    pub fn new(module: &Module, sample_rate: f32, historical: bool, max_loop_count: usize) -> Self {
+        Self::new_from(module, sample_rate, historical, max_loop_count, 0)
+    }
+
+    /// Analyse a song starting at index `table_index` in pattern_order.
+    pub fn new_from(
+        module: &Module,
+        sample_rate: f32,
+        historical: bool,
+        max_loop_count: usize,
+        table_index: usize,
+    ) -> Self {
+        let max_loop_count = max_loop_count.max(1);
+        let mut row_start: Vec<Vec<Option<u64>>> = module
+            .pattern_order
+            .iter()
+            .map(|&p| {
+                let num_rows = if p < module.pattern.len() {
+                    module.get_num_rows(p)
+                } else {
+                    0
+                };
+                vec![None; num_rows]
+            })
+            .collect();
+
+        let mut timeline = Self {
+            sample_rate,
+            total_frames: 0,
+            complete: false,
+            row_start: vec![],
+            loop_point: None,
+        };
+
+        let mut player = XmrsPlayer::new(module, sample_rate, historical);
+        player.set_max_loop_count(max_loop_count);
+        if table_index != 0 && !player.set_start_table_index(table_index) {
+            timeline.complete = true;
+            timeline.row_start = row_start;
+            return timeline;
+        }
+
+        let num_rows: usize = row_start.iter().map(|r| r.len()).sum();
+        let mut rows_budget = (num_rows + 1) * Self::MAX_PASSES_PER_ROW * max_loop_count;
+
+        loop {
+            let chunk = player.next_chunk(usize::MAX);
+
+            if let Some((ti, row)) = player.row_started.take() {
+                if let Some(start) = row_start.get_mut(ti).and_then(|r| r.get_mut(row)) {
+                    if start.is_none() {
+                        *start = Some(player.generated_samples);
+                    }
+                    if timeline.loop_point.is_none() && player.get_loop_count() > 0 {
+                        timeline.loop_point = Some(LoopPoint {
+                            table_index: ti,
+                            row,
+                            frame: player.generated_samples,
+                            target_frame: start.unwrap_or(0),
+                        });
+                    }
+                }
+
+                if rows_budget == 0 {
+                    break;
+                }
+                rows_budget -= 1;
+            }
+
+            match chunk {
+                Some(frames) => player.generated_samples += frames as u64,
+                None => {
+                    timeline.complete = true;
+                    break;
+                }
+            }
+        }
+
+        timeline.total_frames = player.generated_samples;
+        timeline.row_start = row_start;
+        timeline
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Duration {
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    pub fn get_sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Total duration in frames (a (left,right) sample)
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    /// Total duration
+    pub fn duration(&self) -> Duration {
+        self.frames_to_duration(self.total_frames)
+    }
+
+    /// False if the analysis was stopped before the end of the song (endless loop)
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Where the song loops back, if it loops
+    pub fn loop_point(&self) -> Option<LoopPoint> {
+        self.loop_point
+    }
+
+    /// First frame where row at index table_index in pattern_order is played
+    pub fn row_start_frame(&self, table_index: usize, row: usize) -> Option<u64> {
+        *self.row_start.get(table_index)?.get(row)?
+    }
+
+    /// First time where row at index table_index in pattern_order is played
+    pub fn row_start_time(&self, table_index: usize, row: usize) -> Option<Duration> {
+        self.row_start_frame(table_index, row)
+            .map(|f| self.frames_to_duration(f))
+    }
+
+    /// Returns true if index table_index in pattern_order is played
+    pub fn is_reachable(&self, table_index: usize) -> bool {
+        self.row_start
+            .get(table_index)
+            .is_some_and(|r| r.iter().any(|f| f.is_some()))
+    }
+}
@@ -1,20 +1,23 @@
 /// An Instrument Vibrato State
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use xmrs::instr_vibrato::InstrVibrato;
-use xmrs::period_helper::{FrequencyType, PeriodHelper};
+use xmrs::period_helper::FrequencyType;
 
 #[derive(Clone)]
-pub struct StateAutoVibrato<'a> {
-    vibrato: &'a InstrVibrato,
-    period_helper: PeriodHelper,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateAutoVibrato {
+    vibrato: InstrVibrato,
+    freq_type: FrequencyType,
     phase: f32,
     pub current_modulation: f32,
 }
 
-impl<'a> StateAutoVibrato<'a> {
-    pub fn new(vibrato: &'a InstrVibrato, period_helper: PeriodHelper) -> Self {
+impl StateAutoVibrato {
+    pub fn new(vibrato: &InstrVibrato, freq_type: FrequencyType) -> Self {
         let mut sv = Self {
-            vibrato,
-            period_helper,
+            vibrato: *vibrato,
+            freq_type,
             phase: 0.0,
             current_modulation: 0.0,
         };
@@ -45,7 +48,7 @@ impl<'a> StateAutoVibrato<'a> {
 
         self.current_modulation = current_depth * self.vibrato.waveform.value(self.phase);
 
-        if let FrequencyType::AmigaFrequencies = self.period_helper.freq_type {
+        if let FrequencyType::AmigaFrequencies = self.freq_type {
             self.current_modulation /= 4.0;
         }
     }
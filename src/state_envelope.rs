@@ -1,20 +1,23 @@
 /// An Instrument Envelope State
 use crate::helper::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use xmrs::prelude::*;
 
 #[derive(Clone)]
-pub struct StateEnvelope<'a> {
-    env: &'a Envelope,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateEnvelope {
+    enabled: bool,
     default_value: f32,
     pub value: f32,
     pub counter: usize,
 }
 
-impl<'a> StateEnvelope<'a> {
+impl StateEnvelope {
     // value is volume_envelope_volume=1.0 or volume_envelope_panning=0.5
-    pub fn new(env: &'a Envelope, default_value: f32) -> Self {
+    pub fn new(env: &Envelope, default_value: f32) -> Self {
         Self {
-            env,
+            enabled: env.enabled,
             default_value,
             value: default_value,
             counter: 0,
@@ -22,7 +25,7 @@ impl<'a> StateEnvelope<'a> {
     }
 
     pub fn has_volume_envelope(&self) -> bool {
-        self.env.enabled
+        self.enabled
     }
 
     pub fn reset(&mut self) {
@@ -30,8 +33,9 @@ impl<'a> StateEnvelope<'a> {
         self.counter = 0;
     }
 
-    pub fn tick(&mut self, sustained: bool) {
-        let num_points = self.env.point.len();
+    /// `env` must be the envelope used to create this state
+    pub fn tick(&mut self, env: &Envelope, sustained: bool) {
+        let num_points = env.point.len();
 
         if num_points == 0 {
             self.value = 0.0;
@@ -39,22 +43,22 @@ impl<'a> StateEnvelope<'a> {
         }
 
         if num_points == 1 {
-            self.value = self.env.point[0].value;
+            self.value = env.point[0].value;
             clamp_up(&mut self.value);
             return;
         }
 
-        if self.env.loop_enabled {
-            let loop_start = self.env.point[self.env.loop_start_point as usize].frame;
-            let loop_end = self.env.point[self.env.loop_end_point as usize].frame;
+        if env.loop_enabled {
+            let loop_start = env.point[env.loop_start_point].frame;
+            let loop_end = env.point[env.loop_end_point].frame;
             if self.counter >= loop_end {
                 self.counter -= loop_end - loop_start;
             }
         }
 
         for i in 1..num_points {
-            let prev_point = &self.env.point[i - 1];
-            let curr_point = &self.env.point[i];
+            let prev_point = &env.point[i - 1];
+            let curr_point = &env.point[i];
 
             if self.counter == prev_point.frame {
                 self.value = prev_point.value;
@@ -74,8 +78,8 @@ impl<'a> StateEnvelope<'a> {
 
         /* Make sure it is safe to increment frame count */
         if !sustained
-            || !self.env.sustain_enabled
-            || self.counter != self.env.point[self.env.sustain_point as usize].frame
+            || !env.sustain_enabled
+            || self.counter != env.point[env.sustain_point].frame
         {
             self.counter += 1;
         }
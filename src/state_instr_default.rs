@@ -3,37 +3,42 @@ use micromath::F32Ext;
 #[cfg(feature = "libm")]
 use num_traits::float::Float;
 
-use core::ops::Deref;
-
 /// An InstrDefault State
 use crate::helper::*;
+use crate::interpolation::Interpolator;
 use crate::{
-    state_auto_vibrato::StateAutoVibrato, state_envelope::StateEnvelope, state_sample::StateSample,
+    effect_invert_loop::EffectInvertLoop, state_auto_vibrato::StateAutoVibrato,
+    state_envelope::StateEnvelope, state_sample::StateSample,
 };
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use xmrs::prelude::*;
 
-impl<'a> Deref for StateInstrDefault<'a> {
-    type Target = InstrDefault;
-    fn deref(&self) -> &InstrDefault {
-        &self.instr
+/// Returns the InstrDefault at index `num` in the module
+pub fn instr_default(module: &Module, num: usize) -> Option<&InstrDefault> {
+    match &module.instrument.get(num)?.instr_type {
+        InstrumentType::Default(id) => Some(id),
+        _ => None,
     }
 }
 
 #[derive(Clone)]
-pub struct StateInstrDefault<'a> {
-    instr: &'a InstrDefault,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateInstrDefault {
+    /// Instrument index in the module, used for envelopes
     pub num: usize,
+    /// Instrument index in the module, used for everything else (changed by `replace_instr()`)
+    instr: usize,
     /// Output frequency
     rate: f32,
-    period_helper: PeriodHelper,
     /// Sample state
-    pub state_sample: Option<StateSample<'a>>,
+    pub state_sample: Option<StateSample>,
     /// Vibrato state
-    pub state_vibrato: StateAutoVibrato<'a>,
+    pub state_vibrato: StateAutoVibrato,
     /// Volume Envelope state
-    pub envelope_volume: StateEnvelope<'a>,
+    pub envelope_volume: StateEnvelope,
     /// Panning Envelope state
-    pub envelope_panning: StateEnvelope<'a>,
+    pub envelope_panning: StateEnvelope,
 
     // Volume sustained?
     pub sustained: bool,
@@ -49,23 +54,17 @@ pub struct StateInstrDefault<'a> {
     pub panning: f32,
 }
 
-impl<'a> StateInstrDefault<'a> {
-    pub fn new(
-        instr: &'a InstrDefault,
-        num: usize,
-        period_helper: PeriodHelper,
-        rate: f32,
-    ) -> Self {
+impl StateInstrDefault {
+    pub fn new(instr: &InstrDefault, num: usize, freq_type: FrequencyType, rate: f32) -> Self {
         let v = &instr.vibrato;
         let ve = &instr.volume_envelope;
         let pe = &instr.panning_envelope;
         Self {
-            instr,
             num,
+            instr: num,
             rate,
-            period_helper: period_helper.clone(),
             state_sample: None,
-            state_vibrato: StateAutoVibrato::new(v, period_helper),
+            state_vibrato: StateAutoVibrato::new(v, freq_type),
             envelope_volume: StateEnvelope::new(ve, 1.0),
             envelope_panning: StateEnvelope::new(pe, 0.5),
             sustained: true,
@@ -76,11 +75,26 @@ impl<'a> StateInstrDefault<'a> {
         }
     }
 
+    /// Returns the instrument used by this state
+    pub fn get_instr<'m>(&self, module: &'m Module) -> Option<&'m InstrDefault> {
+        instr_default(module, self.instr)
+    }
+
+    /// Returns true if every index still points to the expected module data
+    pub fn is_valid_for(&self, module: &Module) -> bool {
+        instr_default(module, self.num).is_some()
+            && instr_default(module, self.instr).is_some()
+            && self.state_sample.as_ref().is_none_or(|s| {
+                s.get_sample(module)
+                    .is_some_and(|sample| s.is_valid_for(sample))
+            })
+    }
+
     pub fn has_volume_envelope(&self) -> bool {
         self.envelope_volume.has_volume_envelope()
     }
 
-    pub fn replace_instr(&mut self, instr: &'a InstrDefault) {
+    pub fn replace_instr(&mut self, instr: usize) {
         self.instr = instr;
     }
 
@@ -119,13 +133,15 @@ impl<'a> StateInstrDefault<'a> {
         self.volume = 0.0;
     }
 
-    pub fn key_off(&mut self) {
+    pub fn key_off(&mut self, module: &Module) {
         /* Key Off */
         self.sustained = false;
 
         if !self.envelope_volume.has_volume_envelope() {
-            if self.instr.volume_fadeout == 0.0 {
-                self.cut_note();
+            if let Some(instr) = self.get_instr(module) {
+                if instr.volume_fadeout == 0.0 {
+                    self.cut_note();
+                }
             }
         }
     }
@@ -135,18 +151,24 @@ impl<'a> StateInstrDefault<'a> {
             / (self.volume_fadeout + self.envelope_volume.value + self.volume)
     }
 
-    fn envelopes(&mut self) {
+    fn envelopes(&mut self, module: &Module) {
+        let (instr, env) = match (self.get_instr(module), instr_default(module, self.num)) {
+            (Some(instr), Some(env)) => (instr, env),
+            _ => return,
+        };
         // Volume
         if !self.sustained {
-            self.volume_fadeout -= self.instr.volume_fadeout;
+            self.volume_fadeout -= instr.volume_fadeout;
             clamp_down(&mut self.volume_fadeout);
         }
-        if self.instr.volume_envelope.enabled {
-            self.envelope_volume.tick(self.sustained);
+        if instr.volume_envelope.enabled {
+            self.envelope_volume
+                .tick(&env.volume_envelope, self.sustained);
         }
         // Panning
-        if self.instr.panning_envelope.enabled {
-            self.envelope_panning.tick(self.sustained);
+        if instr.panning_envelope.enabled {
+            self.envelope_panning
+                .tick(&env.panning_envelope, self.sustained);
         }
     }
 
@@ -172,60 +194,80 @@ impl<'a> StateInstrDefault<'a> {
         }
     }
 
-    pub fn update_frequency(&mut self, period: f32, arp_note: f32, finetune: f32, semitone: bool) {
+    pub fn update_frequency(
+        &mut self,
+        period_helper: &PeriodHelper,
+        period: f32,
+        arp_note: f32,
+        finetune: f32,
+    ) {
         match &mut self.state_sample {
             Some(s) => {
-                let period_adjusted = self.period_helper.adjust_period(
+                let period_adjusted = period_helper.adjust_period(
                     period,
                     arp_note,
                     finetune + self.state_vibrato.current_modulation,
-                    semitone,
+                    false,
                 );
-                s.set_step(self.period_helper.period_to_frequency(period_adjusted))
+                s.set_step(period_helper.period_to_frequency(period_adjusted))
             }
             None => {}
         }
     }
 
-    pub fn set_note(&mut self, note: Note) -> bool {
+    pub fn set_note(&mut self, module: &Module, note: Note) -> bool {
         if note.is_valid() {
-            let num = self.instr.sample_for_note[note.value() as usize - 1] as usize;
-            return self.select_sample(num);
-        } else {
-            return false;
+            if let Some(instr) = self.get_instr(module) {
+                let num = instr.sample_for_note[note.value() as usize - 1] as usize;
+                return self.select_sample(instr, num);
+            }
         }
+        false
     }
 
-    fn select_sample(&mut self, num: usize) -> bool {
-        if num < self.instr.sample.len() {
-            let sample = &self.instr.sample[num];
-            let state_sample = StateSample::new(sample, self.rate);
+    fn select_sample(&mut self, instr: &InstrDefault, num: usize) -> bool {
+        if num < instr.sample.len() {
+            let sample = &instr.sample[num];
+            let state_sample = StateSample::new(sample, self.instr, num, self.rate);
             self.panning = state_sample.get_panning();
             self.volume = state_sample.get_volume();
             self.volume_orig = self.volume;
             self.state_sample = Some(state_sample);
-            return true;
+            true
         } else {
             self.state_sample = None;
             self.panning = 0.5;
             self.volume = 0.0;
-            return false;
+            false
         }
     }
 
-    pub fn tick(&mut self) {
-        self.envelopes();
+    pub fn tick(&mut self, module: &Module) {
+        self.envelopes(module);
         self.state_vibrato.tick(self.sustained);
     }
-}
 
-impl<'a> Iterator for StateInstrDefault<'a> {
-    type Item = f32;
+    /// Move the sample forward without computing any value, returns the frames played
+    pub fn skip(&mut self, frames: usize) -> usize {
+        match &mut self.state_sample {
+            Some(s) => s.skip(frames),
+            None => 0,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the next sample value, `sample` must be the one returned by `StateSample::get_sample()`
+    pub fn next(
+        &mut self,
+        sample: &Sample,
+        invert: &EffectInvertLoop,
+        interpolator: &Interpolator,
+    ) -> Option<f32> {
         if self.is_enabled() {
             match &mut self.state_sample {
-                Some(s) => s.next(),
+                Some(s) => {
+                    let invert = invert.overlay(s);
+                    s.next_inverted(sample, invert, interpolator)
+                }
                 None => None,
             }
         } else {
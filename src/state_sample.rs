@@ -1,11 +1,43 @@
 /// A Sample State
+use crate::effect_invert_loop::EffectInvertLoop;
 use crate::helper::*;
+use crate::interpolation::{Interpolation, Interpolator, SincKernel};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use xmrs::prelude::*;
 use xmrs::sample::Sample;
 
+/// Reads sample points, through the invert loop overlay if any
+struct Points<'s> {
+    sample: &'s Sample,
+    invert: Option<&'s EffectInvertLoop>,
+}
+
+impl Points<'_> {
+    #[inline(always)]
+    fn at(&self, index: usize) -> f32 {
+        match self.invert {
+            Some(invert) => invert.at(self.sample, index),
+            None => self.sample.at(index),
+        }
+    }
+}
+
 #[derive(Clone)]
-pub struct StateSample<'a> {
-    sample: &'a Sample,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateSample {
+    /// Instrument index in the module
+    instrument: usize,
+    /// Sample index in the instrument
+    sample: usize,
+    /// Sample length, to check the sample and follow loops without reading it
+    len: usize,
+    loop_start: u32,
+    loop_length: u32,
+    flags: LoopType,
+    relative_note: i8,
+    volume: f32,
+    panning: f32,
     finetune: f32,
     /// current seek position
     position: f32,
@@ -17,12 +49,20 @@ pub struct StateSample<'a> {
     rate: f32,
 }
 
-impl<'a> StateSample<'a> {
-    pub fn new(sample: &'a Sample, rate: f32) -> Self {
+impl StateSample {
+    pub fn new(sample: &Sample, instrument: usize, sample_num: usize, rate: f32) -> Self {
         let position = if sample.len() == 0 { -1.0 } else { 0.0 };
         let finetune = sample.finetune;
         Self {
-            sample,
+            instrument,
+            sample: sample_num,
+            len: sample.len(),
+            loop_start: sample.loop_start,
+            loop_length: sample.loop_length,
+            flags: sample.flags,
+            relative_note: sample.relative_note,
+            volume: sample.volume,
+            panning: sample.panning,
             finetune,
             position,
             step: 0.0,
@@ -31,8 +71,29 @@ impl<'a> StateSample<'a> {
         }
     }
 
+    /// Returns the sample read by this state, if the module is the expected one
+    pub fn get_sample<'m>(&self, module: &'m Module) -> Option<&'m Sample> {
+        match &module.instrument.get(self.instrument)?.instr_type {
+            InstrumentType::Default(id) => {
+                id.sample.get(self.sample).filter(|s| s.len() == self.len)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if this state reads `sample` and its position is inside it
+    pub fn is_valid_for(&self, sample: &Sample) -> bool {
+        self.len == sample.len()
+            && self.loop_start == sample.loop_start
+            && self.loop_length == sample.loop_length
+            && self.flags as u8 == sample.flags as u8
+            && self.position.is_finite()
+            && self.position < self.len as f32
+            && self.step.is_finite()
+    }
+
     pub fn reset(&mut self) {
-        self.position = if self.sample.len() == 0 { -1.0 } else { 0.0 };
+        self.position = if self.len == 0 { -1.0 } else { 0.0 };
         self.ping = true;
     }
 
@@ -41,7 +102,7 @@ impl<'a> StateSample<'a> {
     }
 
     pub fn set_position(&mut self, position: usize) {
-        if position >= self.sample.len() {
+        if position >= self.len {
             self.disable();
         } else {
             self.position = position as f32;
@@ -52,25 +113,26 @@ impl<'a> StateSample<'a> {
         self.position >= 0.0
     }
 
-    pub fn disable(&mut self) {
-        self.position = -1.0;
+    /// True if the sample was played since it was (re)started
+    pub fn is_started(&self) -> bool {
+        self.position > 0.0
     }
 
-    pub fn bits(&self) -> u8 {
-        self.sample.bits()
+    pub fn disable(&mut self) {
+        self.position = -1.0;
     }
 
     pub fn get_panning(&self) -> f32 {
-        self.sample.panning
+        self.panning
     }
 
     pub fn get_volume(&self) -> f32 {
-        self.sample.volume
+        self.volume
     }
 
     /// use sample finetune or force if finetune arg!=0
     pub fn get_finetuned_note(&self) -> f32 {
-        self.sample.relative_note as f32 + self.finetune
+        self.relative_note as f32 + self.finetune
     }
 
     /// get finetune only
@@ -82,26 +144,54 @@ impl<'a> StateSample<'a> {
         self.finetune = finetune;
     }
 
-    fn tick(&mut self) -> f32 {
-        let a: u32 = self.position as u32;
-        let b: u32 = a + 1;
-        let t: f32 = self.position - a as f32;
+    /// Sample index in the instrument
+    pub fn get_sample_num(&self) -> usize {
+        self.sample
+    }
+
+    /// Instrument index in the module
+    pub fn get_instrument_num(&self) -> usize {
+        self.instrument
+    }
+
+    /// (loop start, loop length) in sample points, None if the sample doesn't loop
+    pub fn get_loop(&self) -> Option<(usize, usize)> {
+        match self.flags {
+            LoopType::No => None,
+            _ if self.loop_length == 0 => None,
+            _ => Some((self.loop_start as usize, self.loop_length as usize)),
+        }
+    }
 
-        let mut u: f32 = self.sample.at(a as usize);
+    /// Playback frequency in Hz
+    pub fn get_frequency(&self) -> f32 {
+        self.step * self.rate
+    }
+
+    /// Seek position, None if the sample is disabled
+    pub fn get_position(&self) -> Option<f32> {
+        if self.is_enabled() {
+            Some(self.position)
+        } else {
+            None
+        }
+    }
+
+    /// True if the sample is read backward, in a ping-pong loop
+    pub fn is_backward(&self) -> bool {
+        !self.ping
+    }
 
-        let loop_end = self.sample.loop_start + self.sample.loop_length;
+    /// Move the seek position one step further, handling loops
+    fn advance(&mut self) {
+        let loop_end = self.loop_start + self.loop_length;
 
-        let v = match self.sample.flags {
+        match self.flags {
             LoopType::No => {
                 self.position += self.step;
-                if self.position >= self.sample.len() as f32 {
+                if self.position >= self.len as f32 {
                     self.disable();
                 }
-                if b < self.sample.len() as u32 {
-                    self.sample.at(b as usize)
-                } else {
-                    0.0
-                }
             }
             LoopType::Forward => {
                 self.position += self.step;
@@ -111,20 +201,13 @@ impl<'a> StateSample<'a> {
                 // }
 
                 if self.position >= loop_end as f32 {
-                    let delta = (self.position - loop_end as f32) % self.sample.loop_length as f32;
-                    self.position = self.sample.loop_start as f32 + delta;
+                    let delta = (self.position - loop_end as f32) % self.loop_length as f32;
+                    self.position = self.loop_start as f32 + delta;
                 }
                 /* sanity checking */
-                // if self.position >= self.sample.len() as f32 {
-                //     self.position = self.sample.len() as f32 - 1.0;
+                // if self.position >= self.len as f32 {
+                //     self.position = self.len as f32 - 1.0;
                 // }
-
-                let seek = if b >= loop_end {
-                    self.sample.loop_start
-                } else {
-                    b
-                };
-                self.sample.at(seek as usize)
             }
             LoopType::PingPong => {
                 if self.ping {
@@ -136,54 +219,387 @@ impl<'a> StateSample<'a> {
                 if self.ping {
                     if self.position >= loop_end as f32 {
                         self.ping = false;
-                        let delta =
-                            (self.position - loop_end as f32) % self.sample.loop_length as f32;
+                        let delta = (self.position - loop_end as f32) % self.loop_length as f32;
                         self.position = loop_end as f32 - delta;
                     }
                     /* sanity checking */
-                    if self.position >= self.sample.len() as f32 {
+                    if self.position >= self.len as f32 {
                         self.ping = false;
-                        self.position = self.sample.len() as f32 - 1.0;
+                        self.position = self.len as f32 - 1.0;
                     }
-
-                    let seek = if b >= loop_end { a } else { b };
-                    self.sample.at(seek as usize)
                 } else {
-                    if self.position <= self.sample.loop_start as f32 {
+                    if self.position <= self.loop_start as f32 {
                         self.ping = true;
-                        let delta = (self.sample.loop_start as f32 - self.position)
-                            % self.sample.loop_length as f32;
-                        self.position = self.sample.loop_start as f32 + delta;
+                        let delta =
+                            (self.loop_start as f32 - self.position) % self.loop_length as f32;
+                        self.position = self.loop_start as f32 + delta;
                     }
                     /* sanity checking */
                     if self.position <= 0.0 {
                         self.ping = true;
                         self.position = 0.0;
                     }
+                }
+            }
+        }
+    }
+
+    /// Move forward of `frames` steps without computing any value, returns the frames played
+    /// before the sample ended
+    pub fn skip(&mut self, frames: usize) -> usize {
+        for played in 0..frames {
+            if !self.is_enabled() {
+                return played;
+            }
+            self.advance();
+        }
+        frames
+    }
+
+    /// Sample point at `index`, following the loop the way it is played
+    fn point(&self, sample: &Points, index: isize) -> f32 {
+        let len = self.len as isize;
+        let loop_start = self.loop_start as isize;
+        let loop_length = self.loop_length as isize;
+        let loop_end = loop_start + loop_length;
+        let index = match self.flags {
+            _ if loop_length == 0 => index,
+            LoopType::No => {
+                if index >= len {
+                    return 0.0;
+                }
+                index
+            }
+            LoopType::Forward => {
+                if index >= loop_end {
+                    loop_start + (index - loop_end) % loop_length
+                } else {
+                    index
+                }
+            }
+            LoopType::PingPong => {
+                if index >= loop_end || (!self.ping && index < loop_start) {
+                    // mirrored loop, both ends are played twice
+                    let k = (index - loop_start).rem_euclid(2 * loop_length);
+                    if k < loop_length {
+                        loop_start + k
+                    } else {
+                        loop_end - 1 - (k - loop_length)
+                    }
+                } else {
+                    index
+                }
+            }
+        };
+        sample.at(index.min(len - 1).max(0) as usize)
+    }
 
+    /// Windowed sinc stretched by `scale`: its cutoff follows the output Nyquist frequency
+    fn band_limited(&self, sample: &Points, kernel: &SincKernel, scale: f32) -> f32 {
+        let a = self.position as isize;
+        let t = self.position - a as f32;
+        let cutoff = 1.0 / scale;
+        let reach = (kernel.half_width() as f32 * scale) as isize + 1;
+        let mut value = 0.0;
+        let mut weights = 0.0;
+        for k in 1 - reach..=reach {
+            let w = kernel.at((t - k as f32) * cutoff);
+            value += self.point(sample, a + k) * w;
+            weights += w;
+        }
+        value / weights
+    }
+
+    fn tick(&mut self, sample: &Points, interpolator: &Interpolator) -> f32 {
+        if let Some(scale) = interpolator.band_limit_scale(self.step) {
+            let value = self.band_limited(sample, &interpolator.kernel, scale);
+            self.advance();
+            return value;
+        }
+
+        let a = self.position as isize;
+        let t = self.position - a as f32;
+        let value = match interpolator.mode {
+            Interpolation::Nearest => self.point(sample, a),
+            Interpolation::Linear => return self.tick_linear(sample),
+            Interpolation::Hermite => hermite(
+                self.point(sample, a - 1),
+                self.point(sample, a),
+                self.point(sample, a + 1),
+                self.point(sample, a + 2),
+                t,
+            ),
+            Interpolation::Sinc(_) => {
+                let kernel = &interpolator.kernel;
+                let half_width = kernel.half_width() as isize;
+                let mut value = 0.0;
+                let mut weights = 0.0;
+                for k in 1 - half_width..=half_width {
+                    let w = kernel.at(t - k as f32);
+                    value += self.point(sample, a + k) * w;
+                    weights += w;
+                }
+                value / weights
+            }
+        };
+        self.advance();
+        value
+    }
+
+    fn tick_linear(&mut self, sample: &Points) -> f32 {
+        let a: u32 = self.position as u32;
+        let b: u32 = a + 1;
+        let t: f32 = self.position - a as f32;
+
+        let mut u: f32 = sample.at(a as usize);
+
+        let loop_end = self.loop_start + self.loop_length;
+        let ping = self.ping;
+
+        self.advance();
+
+        let v = match self.flags {
+            LoopType::No => {
+                if b < self.len as u32 {
+                    sample.at(b as usize)
+                } else {
+                    0.0
+                }
+            }
+            LoopType::Forward => {
+                let seek = if b >= loop_end { self.loop_start } else { b };
+                sample.at(seek as usize)
+            }
+            LoopType::PingPong => {
+                if ping {
+                    let seek = if b >= loop_end { a } else { b };
+                    sample.at(seek as usize)
+                } else {
                     let v = u;
-                    let seek = if b == 1 || b - 2 <= self.sample.loop_start {
+                    let seek = if b == 1 || b - 2 <= self.loop_start {
                         a
                     } else {
                         b - 2
                     };
-                    u = self.sample.at(seek as usize);
+                    u = sample.at(seek as usize);
                     v
                 }
             }
         };
         lerp(u, v, t)
     }
-}
 
-impl<'a> Iterator for StateSample<'a> {
-    type Item = f32;
+    /// Returns the next value, `sample` must be the one returned by `get_sample()`
+    pub fn next(&mut self, sample: &Sample, interpolator: &Interpolator) -> Option<f32> {
+        self.next_inverted(sample, None, interpolator)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Same as `next()`, reading inverted loop points from `invert`
+    pub fn next_inverted(
+        &mut self,
+        sample: &Sample,
+        invert: Option<&EffectInvertLoop>,
+        interpolator: &Interpolator,
+    ) -> Option<f32> {
         if self.position >= 0.0 {
-            Some(self.tick())
+            Some(self.tick(&Points { sample, invert }, interpolator))
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    const PRE_LOOP: f32 = 0.25;
+    const LOOP: f32 = 0.5;
+    const POST_LOOP: f32 = -0.75;
+
+    /// 32 points before the loop, 32 in the loop and 16 after, each part is constant
+    fn sample(flags: LoopType) -> Sample {
+        let part = |value: f32, len: usize| core::iter::repeat_n((value * 32768.0) as i16, len);
+        Sample {
+            name: String::new(),
+            loop_start: 32,
+            loop_length: 32,
+            volume: 1.0,
+            finetune: 0.0,
+            flags,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Mono16(
+                part(PRE_LOOP, 32)
+                    .chain(part(LOOP, 32))
+                    .chain(part(POST_LOOP, 16))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn modes() -> [Interpolation; 4] {
+        [
+            Interpolation::Nearest,
+            Interpolation::Linear,
+            Interpolation::Hermite,
+            Interpolation::Sinc(8),
+        ]
+    }
+
+    /// Value read at `position`, going forward if `ping` is true
+    fn value_at(
+        sample: &Sample,
+        interpolator: &Interpolator,
+        position: f32,
+        ping: bool,
+    ) -> Option<f32> {
+        let mut state = StateSample::new(sample, 0, 0, 1.0);
+        state.position = position;
+        state.ping = ping;
+        state.step = 0.25;
+        state.next(sample, interpolator)
+    }
+
+    #[test]
+    fn loop_neighbours() {
+        let forward = sample(LoopType::Forward);
+        let pingpong = sample(LoopType::PingPong);
+        // Last loop point: the next points are the loop start for a forward loop, the loop end
+        // again for a ping-pong loop. First loop point backward: the loop start again.
+        let cases = [
+            (&forward, 63.5, true),
+            (&pingpong, 63.5, true),
+            (&pingpong, 32.5, false),
+        ];
+        for mode in modes() {
+            let interpolator = Interpolator::new(mode, None);
+            for &(sample, position, ping) in &cases {
+                let value = value_at(sample, &interpolator, position, ping).unwrap();
+                assert!(
+                    (value - LOOP).abs() < 1e-6,
+                    "{:?} {:?} at {}: {}",
+                    mode,
+                    sample.flags,
+                    position,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn loop_neighbours_band_limited() {
+        // Steps up to 3 points: the widest kernel still reads the loop only
+        for mode in [Interpolation::Linear, Interpolation::Sinc(8)] {
+            let interpolator = Interpolator::new(mode, Some(1.0));
+            for flags in [LoopType::Forward, LoopType::PingPong] {
+                let sample = sample(flags);
+                let mut state = StateSample::new(&sample, 0, 0, 1.0);
+                state.position = 63.5;
+                state.step = 3.0;
+                let value = state.next(&sample, &interpolator).unwrap();
+                assert!(
+                    (value - LOOP).abs() < 1e-6,
+                    "{:?} {:?}: {}",
+                    mode,
+                    flags,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn point_follows_the_loop() {
+        let forward = sample(LoopType::Forward);
+        let state = StateSample::new(&forward, 0, 0, 1.0);
+        let points = Points {
+            sample: &forward,
+            invert: None,
+        };
+        assert_eq!(state.point(&points, 63), LOOP);
+        assert_eq!(state.point(&points, 64), LOOP);
+        assert_eq!(state.point(&points, 64 + 32 * 3 + 5), LOOP);
+        assert_eq!(state.point(&points, -3), PRE_LOOP);
+
+        let pingpong = sample(LoopType::PingPong);
+        let mut state = StateSample::new(&pingpong, 0, 0, 1.0);
+        let points = Points {
+            sample: &pingpong,
+            invert: None,
+        };
+        assert_eq!(state.point(&points, 64), LOOP);
+        assert_eq!(state.point(&points, 31), PRE_LOOP);
+        state.ping = false;
+        assert_eq!(state.point(&points, 31), LOOP);
+
+        let no_loop = sample(LoopType::No);
+        let state = StateSample::new(&no_loop, 0, 0, 1.0);
+        let points = Points {
+            sample: &no_loop,
+            invert: None,
+        };
+        assert_eq!(state.point(&points, 79), POST_LOOP);
+        assert_eq!(state.point(&points, 80), 0.0);
+        assert_eq!(state.point(&points, 1000), 0.0);
+    }
+
+    #[test]
+    fn end_of_a_sample_without_loop() {
+        let sample = sample(LoopType::No);
+        let expected = [
+            (Interpolation::Nearest, POST_LOOP),
+            (Interpolation::Linear, POST_LOOP / 2.0),
+            (
+                Interpolation::Hermite,
+                hermite(POST_LOOP, POST_LOOP, 0.0, 0.0, 0.5),
+            ),
+        ];
+        for (mode, value) in expected {
+            let interpolator = Interpolator::new(mode, None);
+            assert_eq!(value_at(&sample, &interpolator, 79.5, true), Some(value));
+        }
+        let interpolator = Interpolator::new(Interpolation::Sinc(8), None);
+        let value = value_at(&sample, &interpolator, 79.5, true).unwrap();
+        assert!(POST_LOOP < value && value < 0.0);
+
+        // The last step goes past the end
+        for mode in modes() {
+            let interpolator = Interpolator::new(mode, None);
+            let mut state = StateSample::new(&sample, 0, 0, 1.0);
+            state.position = 79.5;
+            state.step = 0.25;
+            assert!(state.next(&sample, &interpolator).is_some());
+            assert!(state.next(&sample, &interpolator).is_some());
+            assert!(state.next(&sample, &interpolator).is_none());
+            assert!(!state.is_enabled());
+        }
+    }
+
+    #[test]
+    fn every_mode_stays_in_the_sample() {
+        for flags in [LoopType::No, LoopType::Forward, LoopType::PingPong] {
+            let sample = sample(flags);
+            for mode in modes() {
+                for band_limit in [None, Some(1.0)] {
+                    let interpolator = Interpolator::new(mode, band_limit);
+                    for step in [0.3, 1.0, 2.7, 7.9, 12.0, 100.0] {
+                        let mut state = StateSample::new(&sample, 0, 0, 1.0);
+                        state.step = step;
+                        let values: Vec<f32> = (0..500)
+                            .map_while(|_| state.next(&sample, &interpolator))
+                            .collect();
+                        assert!(!values.is_empty());
+                        assert!(values.iter().all(|v| v.is_finite() && v.abs() < 2.0));
+                        if !matches!(flags, LoopType::No) {
+                            assert_eq!(values.len(), 500);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
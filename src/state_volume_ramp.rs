@@ -0,0 +1,80 @@
+/// A Volume Ramp State, to avoid clicks on volume changes
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateVolumeRamp {
+    /// (left, right) volume of the next frame
+    volume: [f32; 2],
+    target: [f32; 2],
+    /// added to volume at each frame
+    step: [f32; 2],
+    /// frames before reaching target
+    remaining: usize,
+}
+
+impl StateVolumeRamp {
+    pub fn new(volume: [f32; 2]) -> Self {
+        Self {
+            volume,
+            target: volume,
+            step: [0.0, 0.0],
+            remaining: 0,
+        }
+    }
+
+    /// Go from the current volume to `target` in `frames` frames, immediately if `frames` is 0
+    pub fn set_target(&mut self, target: [f32; 2], frames: usize) {
+        self.target = target;
+        if frames == 0 {
+            self.volume = target;
+            self.remaining = 0;
+        } else {
+            let frames = frames as f32;
+            self.step = [
+                (target[0] - self.volume[0]) / frames,
+                (target[1] - self.volume[1]) / frames,
+            ];
+            self.remaining = frames as usize;
+        }
+    }
+
+    /// Restart from silence, the target is kept
+    pub fn silence(&mut self) {
+        self.volume = [0.0, 0.0];
+        self.remaining = 0;
+    }
+
+    pub fn volume(&self) -> [f32; 2] {
+        self.volume
+    }
+
+    /// True once the target is reached
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Returns the volume of the next frame
+    #[inline(always)]
+    pub fn next(&mut self) -> [f32; 2] {
+        let volume = self.volume;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                self.volume = self.target;
+            } else {
+                self.volume[0] += self.step[0];
+                self.volume[1] += self.step[1];
+            }
+        }
+        volume
+    }
+
+    /// Same as calling `next()` `frames` times, the volume is accumulated the same way
+    pub fn skip(&mut self, frames: usize) {
+        for _ in 0..frames.min(self.remaining) {
+            self.next();
+        }
+    }
+}
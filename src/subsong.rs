@@ -0,0 +1,57 @@
+/// Subsong detection: several tunes stored in one pattern order
+use crate::song_timeline::SongTimeline;
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+use xmrs::prelude::*;
+
+/// A song reachable from an entry point in pattern_order
+#[derive(Clone, Debug)]
+pub struct Subsong {
+    /// Index in pattern_order where the subsong starts
+    pub table_index: usize,
+    /// Length of the subsong, played once
+    pub total_frames: u64,
+    sample_rate: f32,
+}
+
+impl Subsong {
+    /// Walk pattern_order from position 0, then from each position which can't be reached
+    /// by previous subsongs (separated by jumps or unreachable ranges).
+    ///
+    /// A subsong ends at the first separator (pattern index out of range, like S3M `+++` or `---`
+    /// markers) and loops back to its own start, see `XmrsPlayer::select_subsong()`.
+    pub fn scan(module: &Module, sample_rate: f32, historical: bool) -> Vec<Subsong> {
+        let song_length = module.get_song_length();
+        let mut visited = vec![false; song_length];
+        let mut subsongs = vec![];
+
+        for table_index in 0..song_length {
+            if visited[table_index] || module.pattern_order[table_index] >= module.pattern.len() {
+                continue;
+            }
+
+            let timeline = SongTimeline::new_from(module, sample_rate, historical, 1, table_index);
+            for (i, v) in visited.iter_mut().enumerate() {
+                if timeline.is_reachable(i) {
+                    *v = true;
+                }
+            }
+            visited[table_index] = true;
+
+            if timeline.total_frames() != 0 {
+                subsongs.push(Subsong {
+                    table_index,
+                    total_frames: timeline.total_frames(),
+                    sample_rate,
+                });
+            }
+        }
+
+        subsongs
+    }
+
+    /// Length of the subsong, played once
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.total_frames as f64 / self.sample_rate as f64)
+    }
+}
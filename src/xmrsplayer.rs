@@ -1,12 +1,30 @@
 use crate::channel::Channel;
+use crate::channel_info::ChannelInfo;
+use crate::channel_meter::{ChannelMeter, MeterState};
+use crate::compatibility::Compatibility;
 use crate::helper::*;
 use crate::historical_helper::HistoricalHelper;
+use crate::interpolation::{Interpolation, Interpolator};
+use crate::module_ref::ModuleRef;
+use crate::paula::{Paula, PaulaFilter};
+use crate::player_engine::{PlayerController, PlayerEngine};
+use crate::player_observer::PlayerObserver;
+use crate::player_state::{PlayerState, RestoreError};
+use crate::rng::Rng;
+use crate::sfx::SfxVoice;
+use crate::subsong::Subsong;
 use crate::triggerkeep::*;
-use alloc::{vec, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use core::time::Duration;
+#[cfg(feature = "micromath")]
+#[allow(unused_imports)]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
 use xmrs::prelude::*;
 
 pub struct XmrsPlayer<'a> {
-    module: &'a Module,
+    module: ModuleRef<'a>,
     sample_rate: f32,
 
     tempo: u16,
@@ -31,12 +49,18 @@ pub struct XmrsPlayer<'a> {
 
     /// Extra ticks to be played before going to the next row - Used for EEy effect
     extra_ticks: u16,
+    /// (table index, row) of the row started by the last tick0
+    pub(crate) row_started: Option<(usize, usize)>,
 
-    pub channel: Vec<Channel<'a>>,
+    pub channel: Vec<Channel>,
 
     row_loop_count: Vec<Vec<usize>>,
     loop_count: usize,
     max_loop_count: usize,
+    /// Index in pattern_order where the song (or the selected subsong) starts
+    start_table_index: usize,
+    /// Subsongs found by `Subsong::scan()`, scanned once
+    subsongs: Option<Vec<Subsong>>,
 
     /// None if next-one is a left sample, else right sample
     right_sample: Option<f32>,
@@ -45,18 +69,75 @@ pub struct XmrsPlayer<'a> {
     hhelper: Option<HistoricalHelper>,
 
     pause: bool,
+
+    observer: Option<Box<dyn PlayerObserver + Send + 'a>>,
+    /// on_song_end() was already sent
+    song_end_sent: bool,
+    /// Effect reported as a sync marker, in addition to unused effects
+    sync_effect: Option<(u8, Option<u8>)>,
+    interpolator: Interpolator,
+    /// Volume ramping length in frames, 0 if disabled
+    volume_ramp: usize,
+    /// Fade out the previous voice when a note starts
+    note_fadeout: bool,
+    /// Amiga output stage, if enabled
+    paula: Option<Paula>,
+    /// 0.0 to 2.0, 1.0 is as authored
+    stereo_separation: f32,
+    mono: bool,
+    /// Mixer volume of each channel
+    channel_volume: Vec<f32>,
+    /// Mixer panning of each channel, replacing the song panning
+    channel_pan_override: Vec<Option<f32>>,
+    /// Solo channels, every other channel is silent if one is solo
+    channel_solo: Vec<bool>,
+    /// Levels of each channel, empty if metering is disabled
+    meters: Vec<MeterState>,
+    /// Sound effect voices, mixed over the music
+    sfx: Vec<SfxVoice>,
+    /// Raw samples played by sound effect voices
+    sfx_samples: Vec<Sample>,
+    /// Sound effects started, to steal the oldest voice first
+    sfx_count: u64,
+    /// Sound effects tick on their own, to go on while the song is paused or has ended
+    sfx_remaining_in_tick: f32,
+    /// Music channels lowered while a sound effect plays
+    channel_duck: Vec<bool>,
+    /// Mixer volume of ducked channels
+    duck_volume: f32,
+    /// True while a sound effect plays
+    ducking: bool,
+    /// Seed of the random vibrato, tremolo and panbrello waveforms
+    random_seed: u32,
+    compatibility: Compatibility,
+}
+
+impl XmrsPlayer<'static> {
+    /// Create a player owning its module, it can be moved into an audio callback.
+    pub fn from_owned(module: Module, sample_rate: f32, historical: bool) -> Self {
+        Self::from_arc(Arc::new(module), sample_rate, historical)
+    }
+
+    /// Create a player sharing its module with other players or threads.
+    pub fn from_arc(module: Arc<Module>, sample_rate: f32, historical: bool) -> Self {
+        Self::with_module(ModuleRef::Shared(module), sample_rate, historical)
+    }
 }
 
 impl<'a> XmrsPlayer<'a> {
     pub fn new(module: &'a Module, sample_rate: f32, historical: bool) -> Self {
+        Self::with_module(ModuleRef::Borrowed(module), sample_rate, historical)
+    }
+
+    fn with_module(module: ModuleRef<'a>, sample_rate: f32, historical: bool) -> Self {
         let num_channels = module.get_num_channels();
         let hhelper = if historical {
             Some(HistoricalHelper::new(module.default_tempo))
         } else {
             None
         };
+        let channel = vec![Channel::new(&module, sample_rate, hhelper.clone()); num_channels];
         let mut player = Self {
-            module,
             sample_rate,
             tempo: module.default_tempo,
             bpm: module.default_bpm,
@@ -75,18 +156,234 @@ impl<'a> XmrsPlayer<'a> {
             jump_dest: 0,
             jump_row: 0,
             extra_ticks: 0,
-            channel: vec![],
+            row_started: None,
+            channel,
             loop_count: 0,
             max_loop_count: 0,
+            start_table_index: 0,
+            subsongs: None,
             right_sample: None,
             #[cfg(feature = "std")]
             debug: false,
             pause: false,
+            observer: None,
+            song_end_sent: false,
+            sync_effect: None,
+            interpolator: Interpolator::default(),
+            volume_ramp: 0,
+            note_fadeout: false,
+            paula: None,
+            stereo_separation: 1.0,
+            mono: false,
+            channel_volume: vec![1.0; num_channels],
+            channel_pan_override: vec![None; num_channels],
+            channel_solo: vec![false; num_channels],
+            meters: vec![],
+            sfx: vec![],
+            sfx_samples: vec![],
+            sfx_count: 0,
+            sfx_remaining_in_tick: 0.0,
+            channel_duck: vec![false; num_channels],
+            duck_volume: 1.0,
+            ducking: false,
+            random_seed: Rng::DEFAULT_SEED,
+            compatibility: Compatibility::default(),
+            module,
         };
+        player.apply_random_seed();
+        player.apply_channel_settings();
+        player
+    }
 
-        player.channel = vec![Channel::new(module, sample_rate, hhelper.clone()); num_channels];
+    /// Returns the module played
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
 
-        player
+    /// Split the player into a real-time engine, to be moved into the audio callback, and a
+    /// controller sending commands through a lock-free queue of `queue_capacity` commands.
+    pub fn split(self, queue_capacity: usize) -> (PlayerEngine<'a>, PlayerController) {
+        PlayerEngine::new(self, queue_capacity)
+    }
+
+    /// Send playback events to `observer`, replacing the previous one
+    pub fn set_observer(&mut self, observer: Box<dyn PlayerObserver + Send + 'a>) {
+        self.observer = Some(observer);
+    }
+
+    /// Remove the observer and give it back
+    pub fn take_observer(&mut self) -> Option<Box<dyn PlayerObserver + Send + 'a>> {
+        self.observer.take()
+    }
+
+    /// Select how samples are read between two points (default is linear)
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolator = Interpolator::new(interpolation, self.interpolator.band_limit);
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.interpolator.mode
+    }
+
+    /// Low-pass filter samples read faster than `threshold` sample points per frame (high notes,
+    /// low sample rates...) to avoid aliasing. The default is `Some(1.0)`: every frequency the
+    /// output can't carry is filtered. `None` disables it, like trackers which don't filter and
+    /// let high notes alias.
+    ///
+    /// The threshold can't be below 1.0. `Interpolation::Nearest` is never filtered. The filter
+    /// follows the step up to 8 sample points per frame: faster samples are filtered as if read
+    /// at 8 points per frame, and keep some aliasing.
+    pub fn set_band_limit(&mut self, threshold: Option<f32>) {
+        self.interpolator = Interpolator::new(self.interpolator.mode, threshold);
+    }
+
+    pub fn get_band_limit(&self) -> Option<f32> {
+        self.interpolator.band_limit
+    }
+
+    /// Ramp every volume or panning change over `frames` frames to avoid clicks (note cuts,
+    /// retriggers, fast volume slides...). 0 disables ramping, this is the default.
+    pub fn set_volume_ramp(&mut self, frames: usize) {
+        self.volume_ramp = frames;
+        self.apply_channel_settings();
+    }
+
+    pub fn get_volume_ramp(&self) -> usize {
+        self.volume_ramp
+    }
+
+    /// Like FT2, keep playing the previous voice while a new note starts, fading it out during
+    /// the volume ramp. Needs volume ramping, see `set_volume_ramp()`. Default is false.
+    pub fn set_note_fadeout(&mut self, note_fadeout: bool) {
+        self.note_fadeout = note_fadeout;
+        self.apply_channel_settings();
+    }
+
+    pub fn get_note_fadeout(&self) -> bool {
+        self.note_fadeout
+    }
+
+    /// Seed the random vibrato, tremolo and panbrello waveforms (E4y, E7y with y = 3), the same
+    /// seed always renders the same output. Historical mode plays them as square waveforms.
+    /// The seed is applied again when the song is rewound.
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.random_seed = seed;
+        self.apply_random_seed();
+    }
+
+    pub fn get_random_seed(&self) -> u32 {
+        self.random_seed
+    }
+
+    /// Follow the effect semantics of another tracker, default is `Compatibility::Xm`. Modules
+    /// aren't switched to another mode on their own: `Compatibility::guess()` gives one for a
+    /// module loaded by xmrs.
+    ///
+    /// `Compatibility::ProTracker` plays E8x as a coarse panning, EFx as an invert loop, and stops
+    /// portamentos at periods 113 and 856. The invert loop is kept by each channel: unlike
+    /// ProTracker, a channel never hears the points inverted by another one.
+    ///
+    /// `Compatibility::ScreamTracker` plays effects as converted to XM by `import_s3m`, with the
+    /// rules of Scream Tracker 3 the conversion loses: Dxy, Exx, Fxx, Ixy, Kxy, Lxy, Qxy, Rxy and
+    /// Sxy share their memory across patterns, fine and extra fine slides included. Qxy changes
+    /// the volume at each retrigger and Ixy counts its ticks the ST3 way. Volume slides happen on
+    /// the first tick of the row too if `fast_volume_slides` is set: the S3M flag isn't imported.
+    /// Vxx plays as Gxx and Txx as Fxx. Txx tempo slides, S6x and SAx high offsets are Impulse
+    /// Tracker effects ST3 ignores. S8x panning and Uxy are dropped by `import_s3m`, so they aren't
+    /// played.
+    pub fn set_compatibility(&mut self, compatibility: Compatibility) {
+        self.compatibility = compatibility;
+        self.apply_channel_settings();
+    }
+
+    pub fn get_compatibility(&self) -> Compatibility {
+        self.compatibility
+    }
+
+    fn apply_random_seed(&mut self) {
+        for (i, ch) in self.channel.iter_mut().enumerate() {
+            ch.set_random_seed(Rng::derive(self.random_seed, i as u32));
+        }
+    }
+
+    /// Emulate the Amiga Paula output stage, for MOD files: channels are hard panned
+    /// left-right-right-left, then `filter` is applied. The LED filter is toggled by E0x.
+    ///
+    /// `None` disables this mode, this is the default. Interpolation is not changed: Paula reads
+    /// samples like `Interpolation::Nearest`. Filters are applied to rendered frames only, not to
+    /// `samples_from_channels()`. Their memory is part of snapshots, the mode itself is a setting.
+    pub fn set_paula_mode(&mut self, filter: Option<PaulaFilter>) {
+        let led_on = self.is_led_filter_on();
+        self.paula = filter.map(|filter| {
+            let mut paula = Paula::new(filter, self.sample_rate);
+            paula.set_led(led_on);
+            paula
+        });
+        self.apply_channel_settings();
+    }
+
+    pub fn get_paula_mode(&self) -> Option<PaulaFilter> {
+        self.paula.as_ref().map(|p| p.filter())
+    }
+
+    /// True if the Amiga LED filter is enabled by E0x, see `set_paula_mode()`
+    pub fn is_led_filter_on(&self) -> bool {
+        self.paula.as_ref().is_some_and(|p| p.is_led_on())
+    }
+
+    /// Set stereo separation, from 0.0 (mono) to 1.0 (as authored, the default) up to 2.0 (wider).
+    /// Wider stops at hard panning: a hard panned channel stays on its side.
+    pub fn set_stereo_separation(&mut self, separation: f32) {
+        self.stereo_separation = separation.clamp(0.0, 2.0);
+        self.apply_channel_settings();
+    }
+
+    pub fn get_stereo_separation(&self) -> f32 {
+        self.stereo_separation
+    }
+
+    /// Same output on both sides, stereo separation is kept for when mono is disabled
+    pub fn set_mono(&mut self, mono: bool) {
+        self.mono = mono;
+        self.apply_channel_settings();
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono
+    }
+
+    fn apply_channel_settings(&mut self) {
+        let hard_pan = self.paula.is_some();
+        let separation = if self.mono {
+            0.0
+        } else {
+            self.stereo_separation
+        };
+        let solo = self.channel_solo.contains(&true);
+        for (i, ch) in self.channel.iter_mut().enumerate() {
+            ch.set_compatibility(self.compatibility);
+            ch.set_volume_ramp(self.volume_ramp, self.note_fadeout);
+            ch.set_hard_pan(hard_pan.then(|| Paula::side(i)));
+            ch.set_stereo_separation(separation);
+            let volume = if solo && !self.channel_solo[i] {
+                0.0
+            } else if self.ducking && self.channel_duck[i] {
+                self.channel_volume[i] * self.duck_volume
+            } else {
+                self.channel_volume[i]
+            };
+            ch.set_mixer(volume, self.channel_pan_override[i]);
+        }
+        for voice in &mut self.sfx {
+            voice.set_mixer(self.volume_ramp, self.note_fadeout, separation);
+        }
+    }
+
+    /// Also report an effect through `PlayerObserver::on_sync()`: `(effect_type, parameter)`, with
+    /// `effect_type` in XM numbering (for example 0x8 for 8xx or 0xE for Exy), and `parameter` to
+    /// report only this parameter value, or `None` for any. The effect is still played.
+    pub fn set_sync_effect(&mut self, sync_effect: Option<(u8, Option<u8>)>) {
+        self.sync_effect = sync_effect;
     }
 
     #[cfg(feature = "std")]
@@ -100,12 +397,266 @@ impl<'a> XmrsPlayer<'a> {
         }
     }
 
+    /// Play a module instrument on a channel, for previews or sound effects: `note` starts
+    /// with its envelopes, auto-vibrato and fadeout at `volume` (0.0 to 1.0), as if it was on
+    /// a row without any effect. The channel plays it until its pattern starts another note.
+    ///
+    /// Returns false if the channel, the instrument or the note is invalid.
+    pub fn note_on(
+        &mut self,
+        channel_num: usize,
+        instrument: usize,
+        note: Note,
+        volume: f32,
+    ) -> bool {
+        match self.channel.get_mut(channel_num) {
+            Some(ch) => ch.note_on(&self.module, instrument, note, volume),
+            None => false,
+        }
+    }
+
+    /// Release the note of a channel, like a key off in the pattern
+    pub fn note_off(&mut self, channel_num: usize) {
+        if let Some(ch) = self.channel.get_mut(channel_num) {
+            ch.note_off(&self.module);
+        }
+    }
+
+    /// Returns the first channel without any voice playing, if any, for `note_on()`. A released
+    /// note is playing until its fadeout reaches 0.
+    pub fn free_channel(&self) -> Option<usize> {
+        self.channel.iter().position(|ch| !ch.is_busy())
+    }
+
+    /// Reserve `count` sound effect voices, mixed over the music and never touched by the patterns.
+    /// Sound effects playing are stopped.
+    ///
+    /// Sound effects tick at the song BPM but on their own: they go on while the player is paused
+    /// and once the song has ended, with the same volume ramp and stereo separation as the music.
+    pub fn set_sfx_voices(&mut self, count: usize) {
+        self.sfx = vec![SfxVoice::new(&self.module, self.sample_rate, self.hhelper.clone()); count];
+        self.sfx_remaining_in_tick = 0.0;
+        self.ducking = false;
+        self.apply_channel_settings();
+    }
+
+    /// Add a raw sample for `play_sfx_sample()`, returns its index
+    pub fn add_sfx_sample(&mut self, sample: Sample) -> usize {
+        self.sfx_samples.push(sample);
+        self.sfx_samples.len() - 1
+    }
+
+    /// Returns a free voice, or the voice to steal for a sound with `priority`
+    fn sfx_voice_for(&self, priority: u8) -> Option<usize> {
+        self.sfx
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.can_be_stolen(priority))
+            .min_by_key(|(_, v)| v.steal_order())
+            .map(|(i, _)| i)
+    }
+
+    /// Play a module instrument on a sound effect voice, like `note_on()`. `panning` (0.0 is left,
+    /// 1.0 is right) replaces the instrument panning.
+    ///
+    /// If every voice is busy, the oldest one with the lowest priority is stolen, if its priority is
+    /// not higher than `priority`. Returns the voice used, None if the sound can not be played.
+    pub fn play_sfx(
+        &mut self,
+        instrument: usize,
+        note: Note,
+        volume: f32,
+        panning: Option<f32>,
+        priority: u8,
+    ) -> Option<usize> {
+        if !note.is_valid() || instrument >= self.module.instrument.len() {
+            return None;
+        }
+        let voice = self.sfx_voice_for(priority)?;
+        self.sfx_count += 1;
+        let v = &mut self.sfx[voice];
+        v.channel_mut().set_mixer(1.0, panning);
+        v.play_instrument(
+            &self.module,
+            instrument,
+            note,
+            volume,
+            priority,
+            self.sfx_count,
+        )
+        .then_some(voice)
+    }
+
+    /// Play a sample added by `add_sfx_sample()` at `frequency` Hz on a sound effect voice, with
+    /// its loop. Voices are stolen like with `play_sfx()`.
+    pub fn play_sfx_sample(
+        &mut self,
+        sample: usize,
+        frequency: f32,
+        volume: f32,
+        panning: f32,
+        priority: u8,
+    ) -> Option<usize> {
+        let s = self.sfx_samples.get(sample).filter(|s| s.len() > 0)?;
+        let voice = self.sfx_voice_for(priority)?;
+        self.sfx_count += 1;
+        self.sfx[voice]
+            .play_sample(
+                s,
+                sample,
+                frequency,
+                volume,
+                panning,
+                priority,
+                self.sfx_count,
+                self.sample_rate,
+            )
+            .then_some(voice)
+    }
+
+    /// Release the note of a sound effect voice, raw samples are stopped
+    pub fn release_sfx(&mut self, voice: usize) {
+        if let Some(v) = self.sfx.get_mut(voice) {
+            v.release(&self.module);
+        }
+    }
+
+    /// Stop a sound effect voice immediately
+    pub fn stop_sfx(&mut self, voice: usize) {
+        if let Some(v) = self.sfx.get_mut(voice) {
+            v.stop();
+        }
+    }
+
+    pub fn stop_all_sfx(&mut self) {
+        for v in &mut self.sfx {
+            v.stop();
+        }
+    }
+
+    pub fn is_sfx_playing(&self, voice: usize) -> bool {
+        self.sfx.get(voice).is_some_and(|v| v.is_playing())
+    }
+
+    /// Lower `channels` to `volume` (0.0 to 1.0) while a sound effect plays. An empty slice disables ducking.
+    pub fn set_sfx_ducking(&mut self, channels: &[usize], volume: f32) {
+        self.channel_duck.fill(false);
+        for &ch in channels {
+            if let Some(duck) = self.channel_duck.get_mut(ch) {
+                *duck = true;
+            }
+        }
+        self.duck_volume = volume.clamp(0.0, 1.0);
+        self.apply_channel_settings();
+    }
+
+    /// Advance sound effect envelopes, and duck music channels while a sound effect plays
+    fn tick_sfx(&mut self) {
+        for voice in &mut self.sfx {
+            // Never the first tick of a row: sound effects have no row
+            voice.tick(&self.module, 1);
+        }
+        let ducking = self.sfx.iter().any(|v| v.is_playing());
+        if ducking != self.ducking {
+            self.ducking = ducking;
+            self.apply_channel_settings();
+        }
+    }
+
     pub fn mute_all(&mut self, mute: bool) {
         for c in &mut self.channel {
             c.muted = mute;
         }
     }
 
+    /// Set the mixer volume of a channel (default 1.0), applied after every song volume
+    pub fn set_channel_volume(&mut self, channel_num: usize, volume: f32) {
+        if let Some(v) = self.channel_volume.get_mut(channel_num) {
+            *v = volume.max(0.0);
+            self.apply_channel_settings();
+        }
+    }
+
+    pub fn get_channel_volume(&self, channel_num: usize) -> Option<f32> {
+        self.channel_volume.get(channel_num).copied()
+    }
+
+    /// Replace the song panning of a channel by `panning` (0.0 is left, 1.0 is right), `None`
+    /// gives the panning back to the song
+    pub fn set_channel_pan_override(&mut self, channel_num: usize, panning: Option<f32>) {
+        if let Some(p) = self.channel_pan_override.get_mut(channel_num) {
+            *p = panning.map(|p| p.clamp(0.0, 1.0));
+            self.apply_channel_settings();
+        }
+    }
+
+    pub fn get_channel_pan_override(&self, channel_num: usize) -> Option<f32> {
+        *self.channel_pan_override.get(channel_num)?
+    }
+
+    /// Make a channel solo: only solo channels are heard.
+    /// If `exclusive` is true, other channels are no longer solo, else the channel is added to them.
+    pub fn solo_channel(&mut self, channel_num: usize, exclusive: bool) {
+        if channel_num < self.channel_solo.len() {
+            if exclusive {
+                self.channel_solo.fill(false);
+            }
+            self.channel_solo[channel_num] = true;
+            self.apply_channel_settings();
+        }
+    }
+
+    /// Remove a channel from solo channels
+    pub fn unsolo_channel(&mut self, channel_num: usize) {
+        if channel_num < self.channel_solo.len() {
+            self.channel_solo[channel_num] = false;
+            self.apply_channel_settings();
+        }
+    }
+
+    /// No more solo channel, every channel is heard
+    pub fn clear_solo(&mut self) {
+        self.channel_solo.fill(false);
+        self.apply_channel_settings();
+    }
+
+    pub fn is_channel_solo(&self, channel_num: usize) -> bool {
+        self.channel_solo.get(channel_num).copied().unwrap_or(false)
+    }
+
+    /// Measure the level of each channel while rendering, see `channel_meter()`.
+    /// If `scope_frames` is not 0, the last `scope_frames` frames of each channel are also kept,
+    /// see `channel_scope()`.
+    pub fn set_metering(&mut self, enabled: bool, scope_frames: usize) {
+        self.meters = if enabled {
+            vec![MeterState::new(scope_frames); self.channel.len()]
+        } else {
+            vec![]
+        };
+    }
+
+    /// State of a channel after the last tick, None if `channel_num` is out of range
+    pub fn channel_info(&self, channel_num: usize) -> Option<ChannelInfo> {
+        self.channel
+            .get(channel_num)
+            .map(|ch| ch.info(&self.module))
+    }
+
+    /// Levels of a channel during the last `render_*()` call, None if metering is disabled.
+    /// Muted or silent channels have a level of 0.0.
+    pub fn channel_meter(&self, channel_num: usize) -> Option<ChannelMeter> {
+        self.meters.get(channel_num).map(|m| m.meter())
+    }
+
+    /// Last frames of a channel, oldest first, before global volume and amplification.
+    /// Empty if metering is disabled or without scope.
+    pub fn channel_scope(&self, channel_num: usize) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.meters
+            .get(channel_num)
+            .into_iter()
+            .flat_map(|m| m.scope())
+    }
+
     pub fn set_max_loop_count(&mut self, max_loop_count: usize) {
         self.max_loop_count = max_loop_count;
     }
@@ -157,6 +708,207 @@ impl<'a> XmrsPlayer<'a> {
         }
     }
 
+    /// Start the song at index table_index in pattern_order, then rewind
+    pub(crate) fn set_start_table_index(&mut self, table_index: usize) -> bool {
+        match self.module.pattern_order.get(table_index) {
+            Some(&p) if p < self.module.pattern.len() => {
+                self.start_table_index = table_index;
+                self.reset();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns all subsongs found in pattern_order, see `Subsong::scan()`.
+    ///
+    /// The module is scanned at the first call only.
+    pub fn subsongs(&mut self) -> &[Subsong] {
+        let (module, sample_rate, historical) =
+            (&self.module, self.sample_rate, self.hhelper.is_some());
+        self.subsongs
+            .get_or_insert_with(|| Subsong::scan(module, sample_rate, historical))
+    }
+
+    /// Restart the player at the beginning of subsong `n` (0 is the main song).
+    ///
+    /// The subsong ends at the next separator and loops to its own start (or to the module
+    /// restart position if it is inside the subsong).
+    pub fn select_subsong(&mut self, n: usize) -> bool {
+        match self.subsongs().get(n).map(|s| s.table_index) {
+            Some(table_index) => self.set_start_table_index(table_index),
+            None => false,
+        }
+    }
+
+    /// Rewind to the beginning of the song, as if the player was just created.
+    /// User settings (amplification, muted channels, max loop count, pause, volume ramp...) are kept.
+    fn reset(&mut self) {
+        self.tempo = self.module.default_tempo;
+        self.bpm = self.module.default_bpm;
+        self.global_volume = 1.0;
+        self.global_volume_slide_param = 0;
+        self.current_table_index = self.start_table_index;
+        self.current_row = 0;
+        self.current_tick = 0;
+        self.remaining_samples_in_tick = 0.0;
+        self.generated_samples = 0;
+        self.position_jump = false;
+        self.pattern_break = false;
+        self.jump_dest = 0;
+        self.jump_row = 0;
+        self.extra_ticks = 0;
+        self.row_started = None;
+        for row_loop_count in &mut self.row_loop_count {
+            row_loop_count.fill(0);
+        }
+        self.loop_count = 0;
+        self.right_sample = None;
+        if let Some(paula) = &mut self.paula {
+            paula.reset();
+        }
+
+        if let Some(hhelper) = &mut self.hhelper {
+            hhelper.set_tempo(self.module.default_tempo);
+        }
+        for ch in &mut self.channel {
+            let muted = ch.muted;
+            *ch = Channel::new(&self.module, self.sample_rate, self.hhelper.clone());
+            ch.muted = muted;
+        }
+        self.apply_random_seed();
+        self.apply_channel_settings();
+    }
+
+    /// Seek to an absolute frame (a (left,right) sample) from the beginning of the song (or of the selected subsong).
+    ///
+    /// Unlike `goto()`, the song is silently replayed from the start without mixing, so tempo, BPM,
+    /// global volume, effect memories and sample positions are exactly what normal playback would give.
+    ///
+    /// Returns false if the song ends before `frame`.
+    pub fn seek_to_frame(&mut self, frame: u64) -> bool {
+        self.reset();
+
+        // No event while replaying
+        let observer = self.observer.take();
+        let mut found = true;
+        while self.generated_samples < frame {
+            let max_frames = (frame - self.generated_samples).min(usize::MAX as u64) as usize;
+            match self.next_chunk(max_frames) {
+                Some(frames) => {
+                    for ch in &mut self.channel {
+                        ch.skip(frames);
+                    }
+                    self.generated_samples += frames as u64;
+                }
+                None => {
+                    found = false;
+                    break;
+                }
+            }
+        }
+        self.observer = observer;
+        found
+    }
+
+    /// Seek to a time position from the beginning of the song, see `seek_to_frame()`.
+    pub fn seek_to_time(&mut self, time: Duration) -> bool {
+        let frame = time.as_secs_f64() * self.sample_rate as f64;
+        self.seek_to_frame(frame as u64)
+    }
+
+    /// Take a snapshot of the complete player state.
+    ///
+    /// User settings (amplification, muted channels, max loop count, pause, Paula mode, sound effect samples)
+    /// are not part of the snapshot.
+    pub fn snapshot(&self) -> PlayerState {
+        PlayerState {
+            version: PlayerState::FORMAT_VERSION,
+            sample_rate: self.sample_rate,
+            tempo: self.tempo,
+            bpm: self.bpm,
+            global_volume: self.global_volume,
+            global_volume_slide_param: self.global_volume_slide_param,
+            current_table_index: self.current_table_index,
+            current_row: self.current_row,
+            current_tick: self.current_tick,
+            remaining_samples_in_tick: self.remaining_samples_in_tick,
+            generated_samples: self.generated_samples,
+            position_jump: self.position_jump,
+            pattern_break: self.pattern_break,
+            jump_dest: self.jump_dest,
+            jump_row: self.jump_row,
+            extra_ticks: self.extra_ticks,
+            channel: self.channel.clone(),
+            row_loop_count: self.row_loop_count.clone(),
+            loop_count: self.loop_count,
+            start_table_index: self.start_table_index,
+            song_end_sent: self.song_end_sent,
+            right_sample: self.right_sample,
+            hhelper: self.hhelper.clone(),
+            paula: self.paula.as_ref().map(|p| p.state()),
+            sfx: self.sfx.clone(),
+            sfx_count: self.sfx_count,
+            sfx_remaining_in_tick: self.sfx_remaining_in_tick,
+            ducking: self.ducking,
+        }
+    }
+
+    /// Restore a snapshot taken with `snapshot()`, the output is then bit-identical from that point on.
+    ///
+    /// Returns an error, and does nothing, if the snapshot has another format version, or does not match
+    /// the module, the sample rate, the historical mode or the sound effect voices of this player. A snapshot from an untrusted source
+    /// is checked: no index can point out of the module.
+    pub fn restore(&mut self, state: &PlayerState) -> Result<(), RestoreError> {
+        state.check(
+            &self.module,
+            self.sample_rate,
+            self.hhelper.is_some(),
+            &self.sfx_samples,
+            self.sfx.len(),
+        )?;
+
+        self.tempo = state.tempo;
+        self.bpm = state.bpm;
+        self.global_volume = state.global_volume;
+        self.global_volume_slide_param = state.global_volume_slide_param;
+        self.current_table_index = state.current_table_index;
+        self.current_row = state.current_row;
+        self.current_tick = state.current_tick;
+        self.remaining_samples_in_tick = state.remaining_samples_in_tick;
+        self.generated_samples = state.generated_samples;
+        self.position_jump = state.position_jump;
+        self.pattern_break = state.pattern_break;
+        self.jump_dest = state.jump_dest;
+        self.jump_row = state.jump_row;
+        self.extra_ticks = state.extra_ticks;
+        self.row_started = None;
+        for (ch, saved) in self.channel.iter_mut().zip(state.channel.iter()) {
+            let muted = ch.muted;
+            ch.clone_from(saved);
+            ch.muted = muted;
+            ch.reset_period_helper(&self.module);
+        }
+        self.row_loop_count.clone_from(&state.row_loop_count);
+        self.loop_count = state.loop_count;
+        self.start_table_index = state.start_table_index;
+        self.song_end_sent = state.song_end_sent;
+        self.right_sample = state.right_sample;
+        self.hhelper.clone_from(&state.hhelper);
+        if let Some(paula) = &mut self.paula {
+            match &state.paula {
+                Some(saved) => paula.set_state(saved),
+                None => paula.reset(),
+            }
+        }
+        self.sfx.clone_from(&state.sfx);
+        self.sfx_count = state.sfx_count;
+        self.sfx_remaining_in_tick = state.sfx_remaining_in_tick;
+        self.ducking = state.ducking;
+        self.apply_channel_settings();
+        Ok(())
+    }
+
     /// Returns current pattern number in pattern_order
     pub fn get_current_pattern(&self) -> usize {
         self.module.pattern_order[self.current_table_index]
@@ -177,10 +929,30 @@ impl<'a> XmrsPlayer<'a> {
         self.pause = pause;
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.pause
+    }
+
+    /// Where the current subsong loops: the module restart position if it belongs to the subsong,
+    /// else the subsong start. A subsong ends at the first separator or at the end of pattern_order.
+    fn loop_table_index(&self) -> usize {
+        let start = self.start_table_index;
+        let end = self.module.pattern_order[start..]
+            .iter()
+            .position(|&p| p >= self.module.pattern.len())
+            .map_or(self.module.pattern_order.len(), |n| start + n);
+        if (start..end).contains(&self.module.restart_position) {
+            self.module.restart_position
+        } else {
+            start
+        }
+    }
+
     fn post_pattern_change(&mut self) {
-        /* Loop if necessary */
-        if self.current_table_index >= self.module.pattern_order.len() {
-            self.current_table_index = self.module.restart_position;
+        /* Loop if necessary: end of pattern_order or separator */
+        match self.module.pattern_order.get(self.current_table_index) {
+            Some(&p) if p < self.module.pattern.len() => {}
+            _ => self.current_table_index = self.loop_table_index(),
         }
 
         #[cfg(feature = "std")]
@@ -215,6 +987,12 @@ impl<'a> XmrsPlayer<'a> {
             0xE => {
                 /* EXy: Extended command */
                 match pattern_slot.effect_parameter >> 4 {
+                    0x0 => {
+                        /* E0x: Set Amiga LED filter, 0 is on */
+                        if let Some(paula) = &mut self.paula {
+                            paula.set_led(pattern_slot.effect_parameter & 0x01 == 0);
+                        }
+                    }
                     0x6 => {
                         /* E6y: Pattern loop */
                         if pattern_slot.effect_parameter & 0x0F != 0 {
@@ -293,8 +1071,8 @@ impl<'a> XmrsPlayer<'a> {
         let pat_idx = if pat_idx_temp < self.module.pattern.len() {
             pat_idx_temp
         } else {
-            // empty pattern, returning to zero
-            self.current_table_index = 0;
+            // empty pattern, returning to the subsong loop point
+            self.current_table_index = self.loop_table_index();
             self.module.pattern_order[self.current_table_index]
         };
 
@@ -302,6 +1080,7 @@ impl<'a> XmrsPlayer<'a> {
         let mut in_a_loop = false;
 
         let current_row = self.current_row;
+        self.row_started = Some((self.current_table_index, current_row));
         #[cfg(feature = "std")]
         if self.debug {
             print!("{:03X} ", current_row);
@@ -312,7 +1091,7 @@ impl<'a> XmrsPlayer<'a> {
             if self.debug {
                 print!("{:?}", ps);
             }
-            self.channel[ch_index].tick0(ps);
+            self.channel[ch_index].tick0(&self.module, ps);
             self.tick0_global_effects(ch_index);
             if !in_a_loop && self.channel[ch_index].pattern_loop_count > 0 {
                 in_a_loop = true;
@@ -350,7 +1129,7 @@ impl<'a> XmrsPlayer<'a> {
 
     fn tick(&mut self) {
         for ch in &mut self.channel {
-            ch.tick(self.current_tick);
+            ch.tick(&self.module, self.current_tick);
 
             // Specific effect to slide global volume
             if ch.current.effect_type == 0x11 && self.current_tick != 0 {
@@ -370,63 +1149,371 @@ impl<'a> XmrsPlayer<'a> {
         }
     }
 
+    fn process_tick(&mut self) {
+        let tick = self.current_tick;
+        let loop_count = self.loop_count;
+        if tick == 0 {
+            self.tick0();
+        } else {
+            self.tick();
+        }
+        self.send_events(tick, loop_count);
+
+        self.current_tick += 1;
+        if self.current_tick >= self.tempo + self.extra_ticks {
+            self.current_tick = 0;
+            self.extra_ticks = 0;
+        }
+
+        if let Some(hhelper) = &mut self.hhelper {
+            hhelper.set_tempo(self.tempo);
+        }
+        self.remaining_samples_in_tick += self.samples_per_tick();
+    }
+
+    fn samples_per_tick(&self) -> f32 {
+        /* FT2 manual says number of ticks / second = BPM * 0.4 */
+        self.sample_rate / (self.bpm as f32 * 0.4)
+    }
+
+    /// Mix sound effects over `frames` frames from `start`, ticking them on their own clock
+    fn mix_sfx<S: FrameSink>(&mut self, sink: &mut S, start: usize, frames: usize) {
+        let end = start + frames;
+        let mut frame = start;
+        while frame < end && !self.sfx.is_empty() {
+            if self.sfx_remaining_in_tick <= 0.0 {
+                self.tick_sfx();
+                self.sfx_remaining_in_tick += self.samples_per_tick();
+            }
+            let chunk = (self.sfx_remaining_in_tick.ceil() as usize).clamp(1, end - frame);
+            for voice in &mut self.sfx {
+                voice.mix(
+                    &self.module,
+                    &self.sfx_samples,
+                    &self.interpolator,
+                    chunk,
+                    |f, l, r| sink.add(frame + f, l, r),
+                );
+            }
+            self.sfx_remaining_in_tick -= chunk as f32;
+            frame += chunk;
+        }
+    }
+
     fn step(&mut self) {
         if self.remaining_samples_in_tick <= 0.0 {
-            if self.current_tick == 0 {
-                self.tick0();
-            } else {
-                self.tick();
+            self.process_tick();
+        }
+        self.remaining_samples_in_tick -= 1.0;
+    }
+
+    /// Send events of the tick just processed to the observer
+    fn send_events(&mut self, tick: u16, previous_loop_count: usize) {
+        let ended = self.is_song_ended();
+        let observer = match &mut self.observer {
+            Some(observer) => observer,
+            None => {
+                for ch in &mut self.channel {
+                    ch.take_note_on();
+                    ch.take_note_off();
+                }
+                self.song_end_sent = ended;
+                return;
             }
+        };
+        let frame = self.generated_samples;
 
-            self.current_tick += 1;
-            if self.current_tick >= self.tempo + self.extra_ticks {
-                self.current_tick = 0;
-                self.extra_ticks = 0;
+        if self.loop_count > previous_loop_count {
+            observer.on_loop(frame, self.loop_count);
+        }
+        if ended {
+            if !self.song_end_sent {
+                observer.on_song_end(frame);
             }
+            self.song_end_sent = true;
+            return;
+        }
+        self.song_end_sent = false;
 
-            if let Some(hhelper) = &mut self.hhelper {
-                hhelper.set_tempo(self.tempo);
+        if tick == 0 {
+            if let Some((order, row)) = self.row_started {
+                let pattern = self.module.pattern_order[order];
+                observer.on_row(frame, order, pattern, row);
             }
-            /* FT2 manual says number of ticks / second = BPM * 0.4 */
-            self.remaining_samples_in_tick += self.sample_rate / (self.bpm as f32 * 0.4);
         }
-        self.remaining_samples_in_tick -= 1.0;
+        observer.on_tick(frame, tick);
+        for (i, ch) in self.channel.iter_mut().enumerate() {
+            if tick == 0 {
+                let effect_type = ch.current.effect_type;
+                let param = ch.current.effect_parameter;
+                let e8x = effect_type == 0xE && param >> 4 == 0x8;
+                let selected = self
+                    .sync_effect
+                    .is_some_and(|(t, p)| t == effect_type && p.is_none_or(|p| p == param));
+                if Self::is_unused_effect(effect_type) || e8x || selected {
+                    observer.on_sync(frame, i, effect_type, param);
+                }
+            }
+            if ch.take_note_off() {
+                observer.on_note_off(frame, i);
+            }
+            if let Some((instrument, note, volume)) = ch.take_note_on() {
+                observer.on_note_on(frame, i, instrument, note, volume);
+            }
+        }
+    }
+
+    /// XM effects without meaning: Ixx, Jxx, Mxx, Nxx, Oxx, Qxx, Sxx, Uxx, Vxx, Wxx, Zxx and after
+    fn is_unused_effect(effect_type: u8) -> bool {
+        matches!(
+            effect_type,
+            0x12 | 0x13 | 0x16..=0x18 | 0x1A | 0x1C | 0x1E..=0x20 | 0x23..
+        )
+    }
+
+    pub(crate) fn is_song_ended(&self) -> bool {
+        self.max_loop_count > 0 && self.loop_count >= self.max_loop_count
+    }
+
+    /// Process a tick if needed, then returns how many frames (at most `max_frames`) can be
+    /// mixed before the next tick. Returns None if no more frames are available.
+    pub(crate) fn next_chunk(&mut self, max_frames: usize) -> Option<usize> {
+        if self.remaining_samples_in_tick <= 0.0 {
+            self.process_tick();
+        }
+
+        if self.is_song_ended() {
+            return None;
+        }
+
+        // Same arithmetic as step(), one frame at a time, to stay sample accurate
+        let mut frames = 0;
+        loop {
+            self.remaining_samples_in_tick -= 1.0;
+            frames += 1;
+            if frames >= max_frames || self.remaining_samples_in_tick <= 0.0 {
+                return Some(frames);
+            }
+        }
+    }
+
+    /// Mix the whole sink, one tick-sized chunk at a time. Returns the number of frames
+    /// rendered before the end of the song, the remaining frames are filled with silence and
+    /// sound effects. `before_tick` is called at each tick boundary, and once at start if the
+    /// player is paused
+    fn render<S: FrameSink, F: FnMut(&mut Self)>(
+        &mut self,
+        sink: &mut S,
+        apply_volume: bool,
+        mut before_tick: F,
+    ) -> usize {
+        let total = sink.len();
+        sink.clear(0, total);
+        for meter in &mut self.meters {
+            meter.start_block();
+        }
+
+        if self.pause {
+            before_tick(self);
+        }
+
+        let mut done = 0;
+        while done < total && !self.pause {
+            if self.remaining_samples_in_tick <= 0.0 {
+                before_tick(self);
+                if self.pause {
+                    break;
+                }
+            }
+
+            let frames = match self.next_chunk(total - done) {
+                Some(frames) => frames,
+                None => break,
+            };
+
+            let module: &Module = &self.module;
+            let interpolator = &self.interpolator;
+            for (i, ch) in self.channel.iter_mut().enumerate() {
+                let muted = ch.is_muted(module);
+                let mut meter = self.meters.get_mut(i).filter(|_| !muted);
+                let mut fadeout = ch.fadeout_sample_ref(module);
+                let mut sample = ch.sample_ref(module);
+                let mut frame = done;
+                while frame < done + frames && (sample.is_some() || fadeout.is_some()) {
+                    let (mut left, mut right) = (0.0, 0.0);
+                    if let Some(s) = fadeout {
+                        match ch.next_fadeout_frame(s, interpolator) {
+                            Some((l, r)) => (left, right) = (left + l, right + r),
+                            None => fadeout = None,
+                        }
+                    }
+                    if let Some(s) = sample {
+                        match ch.next_frame(s, interpolator) {
+                            Some((l, r)) => (left, right) = (left + l, right + r),
+                            None => sample = None,
+                        }
+                    }
+                    if !muted {
+                        sink.add(frame, left, right);
+                    }
+                    if let Some(meter) = &mut meter {
+                        meter.add(left, right);
+                    }
+                    frame += 1;
+                }
+                if let Some(meter) = self.meters.get_mut(i) {
+                    if muted {
+                        meter.add_silence(frames);
+                        meter.set_voice(None);
+                    } else {
+                        meter.add_silence(done + frames - frame);
+                        meter.set_voice(ch.playing_note());
+                    }
+                }
+            }
+
+            self.mix_sfx(sink, done, frames);
+            self.post_process(sink, done, frames, apply_volume);
+            self.generated_samples += frames as u64;
+            done += frames;
+        }
+
+        // Paused or ended: sound effects go on
+        if done < total && !self.sfx.is_empty() {
+            self.mix_sfx(sink, done, total - done);
+            self.post_process(sink, done, total - done, apply_volume);
+        }
+
+        if self.pause {
+            total
+        } else {
+            done
+        }
     }
 
-    /// Returns samples from each channel before applying global volume and amplification.
-    /// If the function returns None, no more samples are available.
+    /// Global volume and Paula output stage
+    fn post_process<S: FrameSink>(
+        &mut self,
+        sink: &mut S,
+        start: usize,
+        frames: usize,
+        apply_volume: bool,
+    ) {
+        if apply_volume {
+            sink.scale(start, frames, self.global_gain());
+        }
+
+        if let Some(paula) = &mut self.paula {
+            sink.map(start, frames, |left, right| paula.process(left, right));
+        }
+    }
+
+    /// Fill an interleaved stereo buffer (left, right, left, right...) without any allocation.
+    ///
+    /// Returns the number of frames rendered. If the song ends, the rest of the buffer is filled with silence.
+    pub fn render_interleaved(&mut self, buffer: &mut [f32]) -> usize {
+        self.render(&mut Interleaved(buffer), true, |_| {})
+    }
+
+    /// Same as `render_interleaved()`, calling `before_tick` at each tick boundary
+    pub(crate) fn render_interleaved_with<F: FnMut(&mut Self)>(
+        &mut self,
+        buffer: &mut [f32],
+        before_tick: F,
+    ) -> usize {
+        self.render(&mut Interleaved(buffer), true, before_tick)
+    }
+
+    /// Fill two planar buffers, one for each side, without any allocation.
+    ///
+    /// Only the common length of `left` and `right` is rendered. Returns the number of frames rendered.
+    /// If the song ends, the rest of the buffers are filled with silence.
+    pub fn render_planar(&mut self, left: &mut [f32], right: &mut [f32]) -> usize {
+        self.render(&mut Planar(left, right), true, |_| {})
+    }
+
+    /// Same as `render_planar()`, calling `before_tick` at each tick boundary
+    pub(crate) fn render_planar_with<F: FnMut(&mut Self)>(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        before_tick: F,
+    ) -> usize {
+        self.render(&mut Planar(left, right), true, before_tick)
+    }
+
+    /// Returns samples from each channel before applying global volume and amplification,
+    /// followed by one sample for each sound effect voice.
+    /// If the function returns None, the song has ended and no more samples are available.
     ///
     /// In conjunction with the samples_apply_volume() function, this function can be used to replace the iterator or the sample() function if you want to control each channel in fine detail, for example, to create beautiful graphic effects.
     pub fn samples_from_channels(&mut self) -> Option<Vec<(f32, f32)>> {
         if self.pause {
-            return Some(vec![(0.0, 0.0); self.channel.len()]);
+            let mut samples = vec![(0.0, 0.0); self.channel.len()];
+            samples.extend(self.sfx_frame());
+            return Some(samples);
         }
 
         self.step();
 
-        if self.max_loop_count > 0 && self.loop_count >= self.max_loop_count {
+        if self.is_song_ended() {
             return None;
         }
 
-        let samples: Vec<(f32, f32)> = self
+        let module: &Module = &self.module;
+        let interpolator = &self.interpolator;
+        let mut samples: Vec<(f32, f32)> = self
             .channel
             .iter_mut()
-            .map(|ch| match ch.next() {
-                Some(fval) => {
-                    if ch.is_muted() {
-                        (0.0, 0.0)
-                    } else {
-                        fval
-                    }
+            .map(|ch| {
+                let fadeout = match ch.fadeout_sample_ref(module) {
+                    Some(sample) => ch.next_fadeout_frame(sample, interpolator),
+                    None => None,
+                };
+                let frame = match ch.sample_ref(module) {
+                    Some(sample) => ch.next_frame(sample, interpolator),
+                    None => None,
+                };
+                match (frame, fadeout) {
+                    _ if ch.is_muted(module) => (0.0, 0.0),
+                    (Some((l, r)), Some((fl, fr))) => (l + fl, r + fr),
+                    (Some(fval), None) | (None, Some(fval)) => fval,
+                    (None, None) => (0.0, 0.0),
                 }
-                None => (0.0, 0.0),
             })
             .collect();
+        samples.extend(self.sfx_frame());
 
         self.generated_samples += 1;
         Some(samples)
     }
 
+    /// Next frame of each sound effect voice, ticking them on their own clock
+    fn sfx_frame(&mut self) -> Vec<(f32, f32)> {
+        if self.sfx.is_empty() {
+            return vec![];
+        }
+        if self.sfx_remaining_in_tick <= 0.0 {
+            self.tick_sfx();
+            self.sfx_remaining_in_tick += self.samples_per_tick();
+        }
+        self.sfx_remaining_in_tick -= 1.0;
+        let module: &Module = &self.module;
+        let samples = &self.sfx_samples;
+        let interpolator = &self.interpolator;
+        self.sfx
+            .iter_mut()
+            .map(|voice| {
+                let mut frame = (0.0, 0.0);
+                voice.mix(module, samples, interpolator, 1, |_, l, r| {
+                    frame.0 += l;
+                    frame.1 += r;
+                });
+                frame
+            })
+            .collect()
+    }
+
     pub fn samples_to_sample(&mut self, samples: &Vec<(f32, f32)>) -> (f32, f32) {
         let sample = samples
             .into_iter()
@@ -436,24 +1523,26 @@ impl<'a> XmrsPlayer<'a> {
         return (sample.0, sample.1);
     }
 
+    fn global_gain(&self) -> f32 {
+        (self.global_volume * self.amplification) / (self.global_volume + self.amplification)
+    }
+
     /// This function applies volume and amplification to the various channel samples. It is applied to the result of the `samples_from_channels()` function.
     pub fn samples_apply_volume(&mut self, samples: &Vec<(f32, f32)>) -> (f32, f32) {
-        let fgvol =
-            (self.global_volume * self.amplification) / (self.global_volume + self.amplification);
+        let fgvol = self.global_gain();
         let sample = self.samples_to_sample(samples);
         return (sample.0 * fgvol, sample.1 * fgvol);
     }
 
     /// Returns the sum of the samples from the `samples_from_channels()` and `samples_apply_volume()` functions, separating the left channel from the right.
+    ///
+    /// Same result as `samples_from_channels()`, but without any allocation.
     pub fn sample(&mut self, apply_volume: bool) -> Option<(f32, f32)> {
-        if let Some(samples) = self.samples_from_channels() {
-            if apply_volume {
-                return Some(self.samples_apply_volume(&samples));
-            } else {
-                return Some(self.samples_to_sample(&samples));
-            }
+        let mut frame = [0.0; 2];
+        if self.render(&mut Interleaved(&mut frame), apply_volume, |_| {}) == 1 {
+            Some((frame[0], frame[1]))
         } else {
-            return None;
+            None
         }
     }
 
@@ -483,10 +1572,88 @@ impl<'a> Iterator for XmrsPlayer<'a> {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.max_loop_count > 0 && self.loop_count >= self.max_loop_count {
+        if self.is_song_ended() {
             return None;
         } else {
             self.sample_one()
         }
     }
 }
+
+/// Output buffer used by the mixer
+trait FrameSink {
+    /// Number of frames
+    fn len(&self) -> usize;
+    fn clear(&mut self, start: usize, frames: usize);
+    fn add(&mut self, frame: usize, left: f32, right: f32);
+    fn scale(&mut self, start: usize, frames: usize, gain: f32);
+    fn map<F: FnMut(f32, f32) -> (f32, f32)>(&mut self, start: usize, frames: usize, f: F);
+}
+
+/// Interleaved stereo buffer
+struct Interleaved<'b>(&'b mut [f32]);
+
+impl<'b> FrameSink for Interleaved<'b> {
+    fn len(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    fn clear(&mut self, start: usize, frames: usize) {
+        self.0[2 * start..2 * (start + frames)].fill(0.0);
+    }
+
+    #[inline(always)]
+    fn add(&mut self, frame: usize, left: f32, right: f32) {
+        self.0[2 * frame] += left;
+        self.0[2 * frame + 1] += right;
+    }
+
+    fn scale(&mut self, start: usize, frames: usize, gain: f32) {
+        for v in &mut self.0[2 * start..2 * (start + frames)] {
+            *v *= gain;
+        }
+    }
+
+    fn map<F: FnMut(f32, f32) -> (f32, f32)>(&mut self, start: usize, frames: usize, mut f: F) {
+        for frame in self.0[2 * start..2 * (start + frames)].chunks_exact_mut(2) {
+            (frame[0], frame[1]) = f(frame[0], frame[1]);
+        }
+    }
+}
+
+/// Planar stereo buffers
+struct Planar<'b>(&'b mut [f32], &'b mut [f32]);
+
+impl<'b> FrameSink for Planar<'b> {
+    fn len(&self) -> usize {
+        self.0.len().min(self.1.len())
+    }
+
+    fn clear(&mut self, start: usize, frames: usize) {
+        self.0[start..start + frames].fill(0.0);
+        self.1[start..start + frames].fill(0.0);
+    }
+
+    #[inline(always)]
+    fn add(&mut self, frame: usize, left: f32, right: f32) {
+        self.0[frame] += left;
+        self.1[frame] += right;
+    }
+
+    fn scale(&mut self, start: usize, frames: usize, gain: f32) {
+        for v in &mut self.0[start..start + frames] {
+            *v *= gain;
+        }
+        for v in &mut self.1[start..start + frames] {
+            *v *= gain;
+        }
+    }
+
+    fn map<F: FnMut(f32, f32) -> (f32, f32)>(&mut self, start: usize, frames: usize, mut f: F) {
+        let left = &mut self.0[start..start + frames];
+        let right = &mut self.1[start..start + frames];
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            (*l, *r) = f(*l, *r);
+        }
+    }
+}
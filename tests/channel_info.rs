@@ -0,0 +1,192 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+const ROW: usize = 6 * TICK;
+
+/// A note of instrument `instrument` on row 0 of a single channel song
+fn one_note(note: u8, instrument: Instrument) -> Module {
+    let mut module = common::empty_module(1, 16, 1);
+    module.instrument = vec![instrument];
+    let slot = &mut module.pattern[0][0][0];
+    slot.note = Note::try_from(note).unwrap();
+    slot.instrument = 1;
+    module
+}
+
+#[test]
+fn frequency_and_pitch_of_the_note() {
+    // C-4 and C-5 at 8363 Hz for C-4
+    for (note, frequency) in [(49, 8363.0), (61, 16726.0)] {
+        let sample = common::sample(2000, LoopType::Forward, false);
+        let module = one_note(note, common::instrument(sample, false));
+        let mut player = XmrsPlayer::new(&module, 44100.0, false);
+        common::render(&mut player, 10, 10);
+        let info = player.channel_info(0).unwrap();
+        assert_eq!(info.instrument, Some(0));
+        assert_eq!(info.sample, Some(0));
+        assert_eq!(info.note, Note::try_from(note).ok());
+        assert!(
+            (info.frequency - frequency).abs() < 1.0,
+            "{}",
+            info.frequency
+        );
+        assert!(
+            (info.pitch - (note - 1) as f32).abs() < 1e-3,
+            "{}",
+            info.pitch
+        );
+    }
+    let module = common::empty_module(1, 16, 1);
+    let player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_eq!(player.channel_info(0).unwrap().frequency, 0.0);
+    assert_eq!(player.channel_info(1), None);
+}
+
+#[test]
+fn volume_follows_envelope_and_fadeout() {
+    let sample = common::sample(2000, LoopType::Forward, false);
+    let mut module = one_note(49, common::instrument(sample, true));
+    // Key off at row 2
+    module.pattern[0][2][0].note = Note::KeyOff;
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+
+    // Volume envelope: 0.2 at tick 0, up to 1.0 at tick 8, then down. It moves from tick 1.
+    common::render(&mut player, TICK + 1, TICK);
+    let info = player.channel_info(0).unwrap();
+    let position = info.volume_envelope.unwrap();
+    assert_eq!(info.panning_envelope, None);
+    assert_eq!((info.fadeout, info.released), (1.0, false));
+    let start = info.volume;
+    assert!(start > 0.0);
+    // Four ticks later, in the same row
+    common::render(&mut player, 4 * TICK, TICK);
+    let info = player.channel_info(0).unwrap();
+    assert_eq!(info.volume_envelope, Some(position + 4));
+    assert!(info.volume > start);
+
+    // Released at row 2: the fadeout goes down, and the volume with it
+    common::render(&mut player, 2 * ROW - 5 * TICK, TICK);
+    let mut previous = player.channel_info(0).unwrap();
+    assert!(previous.released);
+    for _ in 0..80 {
+        common::render(&mut player, TICK, TICK);
+        let info = player.channel_info(0).unwrap();
+        assert!(info.released);
+        assert!(info.fadeout <= previous.fadeout);
+        assert!(info.volume <= previous.volume);
+        previous = info;
+    }
+    assert_eq!(previous.fadeout, 0.0);
+    assert_eq!(previous.volume, 0.0);
+}
+
+#[test]
+fn sample_position_and_direction() {
+    let sample = common::sample(400, LoopType::PingPong, false);
+    let module = one_note(49, common::instrument(sample, false));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 1, 1);
+    let info = player.channel_info(0).unwrap();
+    assert_eq!(info.direction, LoopDirection::Forward);
+    let step = info.frequency / 44100.0;
+
+    // Loop from 100 to 300, forward then backward
+    let mut position = info.position.unwrap();
+    let mut backward = false;
+    for _ in 0..2000 {
+        common::render(&mut player, 1, 1);
+        let info = player.channel_info(0).unwrap();
+        let now = info.position.unwrap();
+        match info.direction {
+            LoopDirection::Forward if !backward => {
+                assert!((now - position - step).abs() < 1e-3)
+            }
+            LoopDirection::Backward => {
+                backward = true;
+                assert!((100.0..=300.0).contains(&now), "{now}");
+            }
+            _ => {}
+        }
+        position = now;
+    }
+    assert!(backward);
+
+    let module = common::empty_module(1, 16, 1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 1, 1);
+    let info = player.channel_info(0).unwrap();
+    assert_eq!(
+        (info.position, info.direction),
+        (None, LoopDirection::Stopped)
+    );
+}
+
+#[test]
+fn active_effects_of_the_row() {
+    let sample = common::sample(2000, LoopType::Forward, false);
+    let mut module = one_note(49, common::instrument(sample, false));
+    let rows: [(u8, u8, u8); 12] = [
+        (0x0, 0x37, 0x00),
+        (0x1, 0x02, 0x00),
+        (0x3, 0x04, 0x00),
+        (0x4, 0x44, 0x00),
+        (0x7, 0x44, 0x00),
+        (0xA, 0x01, 0x00),
+        (0x0, 0x00, 0x62),
+        (0xE, 0x93, 0x00),
+        (0xE, 0xC3, 0x00),
+        (0x22, 0x44, 0x00),
+        (0x19, 0x10, 0x00),
+        (0x14, 0x03, 0x00),
+    ];
+    for (row, &(effect_type, effect_parameter, volume)) in rows.iter().enumerate() {
+        let slot = &mut module.pattern[0][row + 1][0];
+        slot.effect_type = effect_type;
+        slot.effect_parameter = effect_parameter;
+        slot.volume = volume;
+    }
+
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 1, 1);
+    assert_eq!(
+        player.channel_info(0).unwrap().effects,
+        ActiveEffects::default()
+    );
+    let flags = |e: ActiveEffects| {
+        [
+            e.arpeggio,
+            e.portamento,
+            e.tone_portamento,
+            e.vibrato,
+            e.tremolo,
+            e.volume_slide,
+            e.volume_slide,
+            e.retrigger,
+            e.note_cut,
+            e.panbrello,
+            e.panning_slide,
+            e.key_off,
+        ]
+    };
+    for (row, &(effect_type, effect_parameter, volume)) in rows.iter().enumerate() {
+        common::render(&mut player, ROW, ROW);
+        let info = player.channel_info(0).unwrap();
+        assert_eq!(info.effect, (effect_type, effect_parameter));
+        assert_eq!(info.volume_column, volume);
+        let flags = flags(info.effects);
+        assert!(flags[row], "row {}", row + 1);
+        // Only this one, the volume column test shares its flag with Axy
+        let others = flags.iter().filter(|&&f| f).count();
+        assert_eq!(
+            others,
+            if matches!(row, 5 | 6) { 2 } else { 1 },
+            "row {}",
+            row + 1
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Small modules built in memory for the integration tests
+#![allow(dead_code)]
+
+use xmrs::envelope::{Envelope, EnvelopePoint};
+use xmrs::instr_default::InstrDefault;
+use xmrs::prelude::*;
+use xmrs::sample::{LoopType, Sample, SampleDataType};
+
+/// Linear congruential generator, the tests must not depend on anything random
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+/// A sine-like sample of `len` points
+pub fn sample(len: usize, flags: LoopType, eight_bits: bool) -> Sample {
+    let wave = (0..len).map(|x| ((x as f32 * 0.07).sin() * 20000.0) as i16);
+    let data = if eight_bits {
+        SampleDataType::Mono8(wave.map(|v| (v >> 8) as i8).collect())
+    } else {
+        SampleDataType::Mono16(wave.collect())
+    };
+    Sample {
+        name: String::new(),
+        loop_start: len as u32 / 4,
+        loop_length: len as u32 / 2,
+        volume: 0.8,
+        finetune: 0.0,
+        flags,
+        panning: 0.5,
+        relative_note: 0,
+        data,
+    }
+}
+
+/// A looped sine of `cycles` per sample point, 1000 points long
+pub fn sine(cycles: f32) -> Sample {
+    let mut sample = sample(1000, LoopType::Forward, false);
+    let data = (0..1000)
+        .map(|i| (16384.0 * (core::f32::consts::TAU * cycles * i as f32).sin()) as i16)
+        .collect();
+    sample.data = SampleDataType::Mono16(data);
+    sample.loop_start = 0;
+    sample.loop_length = 1000;
+    sample
+}
+
+/// A constant looped sample: the output only moves with the voice volume
+pub fn dc() -> Sample {
+    let mut sample = sample(1000, LoopType::Forward, false);
+    sample.data = SampleDataType::Mono16(vec![16384; 1000]);
+    sample.loop_start = 0;
+    sample.loop_length = 1000;
+    sample
+}
+
+/// An instrument playing `sample` on every note
+pub fn instrument(sample: Sample, envelope: bool) -> Instrument {
+    let mut id = InstrDefault::default();
+    id.sample.push(sample);
+    if envelope {
+        id.volume_envelope = Envelope {
+            enabled: true,
+            point: vec![
+                EnvelopePoint {
+                    frame: 0,
+                    value: 0.2,
+                },
+                EnvelopePoint {
+                    frame: 8,
+                    value: 1.0,
+                },
+                EnvelopePoint {
+                    frame: 40,
+                    value: 0.4,
+                },
+            ],
+            sustain_enabled: true,
+            sustain_point: 2,
+            loop_enabled: false,
+            loop_start_point: 0,
+            loop_end_point: 0,
+        };
+        id.volume_fadeout = 0.02;
+    }
+    Instrument {
+        instr_type: InstrumentType::Default(id),
+        ..Default::default()
+    }
+}
+
+/// Four instruments with forward, ping-pong and no loop, 8 and 16 bits
+pub fn instruments() -> Vec<Instrument> {
+    vec![
+        instrument(sample(2000, LoopType::Forward, false), true),
+        instrument(sample(1500, LoopType::PingPong, true), false),
+        instrument(sample(800, LoopType::No, false), false),
+        instrument(sample(3000, LoopType::Forward, true), true),
+    ]
+}
+
+/// A module of `channels` channels and `rows` rows per pattern, with empty patterns
+pub fn empty_module(channels: usize, rows: usize, patterns: usize) -> Module {
+    Module {
+        frequency_type: FrequencyType::LinearFrequencies,
+        default_tempo: 6,
+        default_bpm: 125,
+        instrument: instruments(),
+        pattern: vec![vec![vec![PatternSlot::default(); channels]; rows]; patterns],
+        pattern_order: (0..patterns).collect(),
+        ..Default::default()
+    }
+}
+
+/// A busy song with notes and common effects, `seed` changes the notes and effects
+pub fn busy_module(seed: u64, amiga: bool) -> Module {
+    let mut r = Lcg(seed);
+    let mut module = empty_module(4, 32, 3);
+    if amiga {
+        module.frequency_type = FrequencyType::AmigaFrequencies;
+    }
+    // Effects with harmless parameters: no jump nor pattern break
+    let effects: [u8; 14] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xA, 0xC, 0x19, 0x1B];
+    for pattern in &mut module.pattern {
+        for row in pattern.iter_mut() {
+            for slot in row.iter_mut() {
+                if r.below(3) == 0 {
+                    slot.note = Note::try_from((25 + r.below(48)) as u8).unwrap();
+                    slot.instrument = 1 + r.below(4) as u8;
+                } else if r.below(16) == 0 {
+                    slot.note = Note::KeyOff;
+                }
+                if r.below(4) == 0 {
+                    slot.volume = 0x10 + r.below(0x41) as u8;
+                }
+                if r.below(3) == 0 {
+                    slot.effect_type = effects[r.below(effects.len() as u32) as usize];
+                    slot.effect_parameter = r.below(256) as u8;
+                    if slot.effect_type == 9 {
+                        slot.effect_parameter &= 0x03;
+                    }
+                } else if r.below(8) == 0 {
+                    // E9x retrigger, ECx note cut
+                    slot.effect_type = 0xE;
+                    slot.effect_parameter = [0x92, 0xC3][r.below(2) as usize];
+                }
+            }
+        }
+    }
+    module.pattern[1][0][0].effect_type = 0xF;
+    module.pattern[1][0][0].effect_parameter = 4;
+    module.pattern[2][0][0].effect_type = 0xF;
+    module.pattern[2][0][0].effect_parameter = 140;
+    module
+}
+
+/// Render `frames` frames in chunks of `chunk` frames
+pub fn render(
+    player: &mut xmrsplayer::prelude::XmrsPlayer,
+    frames: usize,
+    chunk: usize,
+) -> Vec<f32> {
+    let mut out = vec![0.0; 2 * frames];
+    for buffer in out.chunks_mut(2 * chunk) {
+        player.render_interleaved(buffer);
+    }
+    out
+}
+
+/// Sum of the squared samples
+pub fn energy(frames: &[f32]) -> f32 {
+    frames.iter().map(|v| v * v).sum()
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+/// C-4 with `effect` on every row, of a module loaded by `import_amiga`
+fn amiga_module(effect: (u8, u8)) -> Module {
+    let mut module = common::empty_module(1, 16, 1);
+    module.comment = "XmRs reader".into();
+    module.frequency_type = FrequencyType::AmigaFrequencies;
+    module.instrument = vec![common::instrument(
+        common::sample(2000, LoopType::Forward, false),
+        false,
+    )];
+    let rows = &mut module.pattern[0];
+    rows[0][0].note = Note::try_from(49).unwrap();
+    rows[0][0].instrument = 1;
+    for row in rows.iter_mut() {
+        (row[0].effect_type, row[0].effect_parameter) = effect;
+    }
+    module
+}
+
+fn rendered(module: &Module, compatibility: Option<Compatibility>) -> Vec<f32> {
+    let mut player = XmrsPlayer::new(module, 44100.0, false);
+    if let Some(compatibility) = compatibility {
+        player.set_compatibility(compatibility);
+    }
+    common::render(&mut player, 16 * 6 * TICK, 1000)
+}
+
+/// Points of the sample of instrument 0
+fn points(module: &Module) -> Vec<f32> {
+    match &module.instrument[0].instr_type {
+        InstrumentType::Default(id) => (0..id.sample[0].len())
+            .map(|i| id.sample[0].at(i))
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn imported_modules_play_as_xm_by_default() {
+    let module = amiga_module((0xE, 0xFF));
+    assert_eq!(Compatibility::guess(&module), Compatibility::ProTracker);
+    assert_eq!(
+        Compatibility::guess(&common::empty_module(1, 16, 1)),
+        Compatibility::Xm
+    );
+    let player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_eq!(player.get_compatibility(), Compatibility::Xm);
+    assert_eq!(
+        rendered(&module, None),
+        rendered(&module, Some(Compatibility::Xm))
+    );
+}
+
+#[test]
+fn invert_loop_changes_the_output_not_the_module() {
+    // EFF: invert loop at full speed
+    let module = amiga_module((0xE, 0xFF));
+    let before = points(&module);
+    let xm = rendered(&module, Some(Compatibility::Xm));
+    let protracker = rendered(&module, Some(Compatibility::ProTracker));
+    let differing = xm
+        .iter()
+        .zip(&protracker)
+        .filter(|(a, b)| (*a - *b).abs() > 1e-6)
+        .count();
+    // One more point of the loop inverted each tick
+    assert!(differing > xm.len() / 100, "{differing}");
+    assert_eq!(
+        rendered(&module, Some(Compatibility::ProTracker)),
+        protracker
+    );
+    assert_eq!(points(&module), before);
+
+    // Two players of the same module don't hear each other's inverted points
+    let mut first = XmrsPlayer::new(&module, 44100.0, false);
+    let mut second = XmrsPlayer::new(&module, 44100.0, false);
+    first.set_compatibility(Compatibility::ProTracker);
+    second.set_compatibility(Compatibility::ProTracker);
+    common::render(&mut first, 8 * 6 * TICK, 1000);
+    assert_eq!(common::render(&mut second, 16 * 6 * TICK, 1000), protracker);
+}
+
+#[test]
+fn protracker_periods_stop_at_113_and_856() {
+    // 1FF and 2FF: fast portamentos up and down, over 8 rows
+    let mut limits = vec![];
+    for effect in [(0x1, 0xFF), (0x2, 0xFF)] {
+        let module = amiga_module(effect);
+        let mut frequencies = vec![];
+        for compatibility in [Compatibility::Xm, Compatibility::ProTracker] {
+            let mut player = XmrsPlayer::new(&module, 44100.0, false);
+            player.set_compatibility(compatibility);
+            common::render(&mut player, 8 * 6 * TICK, TICK);
+            let held = player.channel_info(0).unwrap().frequency;
+            common::render(&mut player, 3 * TICK, TICK);
+            frequencies.push((held, player.channel_info(0).unwrap().frequency));
+        }
+        let (xm, protracker) = (frequencies[0], frequencies[1]);
+        // Held at the limit, where XM goes further
+        assert_eq!(protracker.0, protracker.1, "{effect:?}");
+        assert!(protracker.0 > 0.0);
+        if effect.0 == 0x1 {
+            assert!(xm.0 > protracker.0, "{xm:?} {protracker:?}");
+        } else {
+            assert!(xm.0 < protracker.0, "{xm:?} {protracker:?}");
+        }
+        limits.push(protracker.0);
+    }
+    // Frequencies are inversely proportional to periods
+    let ratio = limits[0] / limits[1];
+    assert!((ratio / (856.0 / 113.0) - 1.0).abs() < 0.05, "{ratio}");
+}
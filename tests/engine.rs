@@ -0,0 +1,30 @@
+mod common;
+
+use xmrsplayer::prelude::*;
+
+#[test]
+fn commands_are_applied_after_the_song_end() {
+    let module = common::empty_module(4, 4, 1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_max_loop_count(1);
+    let (mut engine, controller) = player.split(4);
+
+    let mut buffer = vec![0.0; 2 * 882];
+    for _ in 0..4 * 6 + 1 {
+        engine.render_interleaved(&mut buffer);
+    }
+    assert!(controller.status().ended);
+
+    for i in 0..4 * controller.capacity() {
+        let pause = i % 2 == 0;
+        assert!(controller.pause(pause), "queue full at command {}", i);
+        engine.render_interleaved(&mut buffer);
+        assert_eq!(controller.status().paused, pause);
+    }
+
+    assert!(controller.pause(false));
+    assert!(controller.set_max_loop_count(0));
+    engine.render_planar(&mut [0.0; 882], &mut [0.0; 882]);
+    let status = controller.status();
+    assert!(!status.ended && !status.paused);
+}
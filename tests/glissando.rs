@@ -0,0 +1,103 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+/// C-4 with E31 then a slow tone portamento up to E-4 at row 1, of a sample finetuned by a
+/// quarter of a semitone. `vibrato` adds a volume column vibrato to the portamento.
+fn slide(frequency_type: FrequencyType, glissando: bool, vibrato: bool) -> Module {
+    let mut sample = common::sample(2000, LoopType::Forward, false);
+    sample.finetune = 0.25;
+    let mut module = common::empty_module(1, 16, 1);
+    module.frequency_type = frequency_type;
+    module.instrument = vec![common::instrument(sample, false)];
+    let rows = &mut module.pattern[0];
+    rows[0][0].note = Note::try_from(49).unwrap();
+    rows[0][0].instrument = 1;
+    if glissando {
+        rows[0][0].effect_type = 0xE;
+        rows[0][0].effect_parameter = 0x31;
+    }
+    // Ax: vibrato speed
+    rows[0][0].volume = 0xA4;
+    for row in &mut rows[1..4] {
+        row[0].effect_type = 0x3;
+        row[0].effect_parameter = 0x08;
+        if vibrato {
+            // Bx: vibrato depth
+            row[0].volume = 0xB2;
+        }
+    }
+    rows[1][0].note = Note::try_from(53).unwrap();
+    module
+}
+
+/// `ChannelInfo::pitch` at each tick of rows 1 to 3
+fn pitches(module: &Module, historical: bool) -> Vec<f32> {
+    let mut player = XmrsPlayer::new(module, 44100.0, historical);
+    common::render(&mut player, 6 * TICK, TICK);
+    (0..18)
+        .map(|_| {
+            common::render(&mut player, TICK, TICK);
+            player.channel_info(0).unwrap().pitch
+        })
+        .collect()
+}
+
+fn is_semitone(pitch: f32) -> bool {
+    (pitch - pitch.round()).abs() < 1e-3
+}
+
+#[test]
+fn glissando_snaps_to_finetuned_semitones() {
+    for frequency_type in [
+        FrequencyType::LinearFrequencies,
+        FrequencyType::AmigaFrequencies,
+    ] {
+        for historical in [false, true] {
+            let what = format!("{frequency_type:?} historical {historical}");
+            let smooth = pitches(&slide(frequency_type, false, false), historical);
+            assert!(smooth.iter().any(|&p| !is_semitone(p)), "{what} {smooth:?}");
+
+            let stepped = pitches(&slide(frequency_type, true, false), historical);
+            assert!(
+                stepped.iter().all(|&p| is_semitone(p)),
+                "{what} {stepped:?}"
+            );
+            assert!(
+                stepped.windows(2).all(|w| w[1] >= w[0]),
+                "{what} {stepped:?}"
+            );
+            // From C-4 up to E-4, through every semitone
+            assert_eq!(stepped.first().map(|p| p.round()), Some(48.0), "{what}");
+            assert_eq!(stepped.last().map(|p| p.round()), Some(52.0), "{what}");
+            for semitone in 48..=52 {
+                let reached = stepped.iter().any(|p| (p - semitone as f32).abs() < 1e-3);
+                assert!(reached, "{what} {stepped:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn vibrato_is_added_after_the_glissando() {
+    let stepped = pitches(&slide(FrequencyType::LinearFrequencies, true, false), false);
+    let vibrato = pitches(&slide(FrequencyType::LinearFrequencies, true, true), false);
+    // The portamento goes on the same way, the vibrato is not snapped
+    let offsets: Vec<f32> = vibrato.iter().zip(&stepped).map(|(v, s)| v - s).collect();
+    assert!(offsets.iter().any(|&o| o.abs() > 1e-3), "{offsets:?}");
+    assert!(offsets.iter().any(|&o| !is_semitone(o)), "{offsets:?}");
+    assert!(
+        offsets.iter().all(|&o| o.abs() <= 1.0 + 1e-3),
+        "{offsets:?}"
+    );
+
+    // FT2: the tone portamento of the effect column cancels the volume column vibrato
+    let stepped = pitches(&slide(FrequencyType::LinearFrequencies, true, false), true);
+    let vibrato = pitches(&slide(FrequencyType::LinearFrequencies, true, true), true);
+    assert_eq!(stepped, vibrato);
+}
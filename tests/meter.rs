@@ -0,0 +1,78 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// Start of row 1 at speed 6 and 125 BPM, at 44100 Hz
+const ROW: usize = 6 * 882;
+
+/// Constant note on channel 0 at half volume, silenced at row 1; channel 1 is empty
+fn dc_module() -> Module {
+    let mut module = common::empty_module(2, 16, 1);
+    module.instrument = vec![common::instrument(common::dc(), false)];
+    let slot = &mut module.pattern[0][0][0];
+    slot.note = Note::try_from(49).unwrap();
+    slot.instrument = 1;
+    // C20: half volume, then C00
+    slot.effect_type = 0xC;
+    slot.effect_parameter = 0x20;
+    module.pattern[0][1][0].effect_type = 0xC;
+    module.pattern[0][1][0].effect_parameter = 0x00;
+    module
+}
+
+fn assert_near(value: f32, expected: f32) {
+    assert!((value - expected).abs() < 1e-4, "{value} {expected}");
+}
+
+#[test]
+fn meters_measure_the_last_block() {
+    let module = dc_module();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_eq!(player.channel_meter(0), None);
+    player.set_metering(true, 64);
+
+    // Sample at 0.5, at the channel volume, centered with the constant power panning law
+    common::render(&mut player, 1000, 1000);
+    let volume = player.channel_info(0).unwrap().volume;
+    assert!(volume > 0.0);
+    let level = 0.5 * volume * 0.5_f32.sqrt();
+    let meter = player.channel_meter(0).unwrap();
+    assert_near(meter.peak, level);
+    assert_near(meter.rms, level);
+    assert!(meter.active);
+    assert_eq!(meter.note, Note::try_from(49).ok());
+    assert!(player
+        .channel_scope(0)
+        .all(|(l, r)| (l - level).abs() < 1e-4 && (r - level).abs() < 1e-4));
+    assert_eq!(player.channel_meter(1), Some(ChannelMeter::default()));
+    assert_eq!(player.channel_scope(1).count(), 64);
+
+    // A block ending 32 frames after C00
+    common::render(&mut player, ROW - 1000 - 292, 1000);
+    common::render(&mut player, 324, 324);
+    let meter = player.channel_meter(0).unwrap();
+    assert_near(meter.peak, level);
+    assert_near(meter.rms, level * (292.0_f32 / 324.0).sqrt());
+    // Still playing, at volume 0
+    assert!(meter.active);
+
+    let scope: Vec<(f32, f32)> = player.channel_scope(0).collect();
+    assert_eq!(scope.len(), 64);
+    assert!(scope[..32].iter().all(|&(l, _)| (l - level).abs() < 1e-4));
+    assert!(scope[32..].iter().all(|&s| s == (0.0, 0.0)));
+
+    // Silent block
+    common::render(&mut player, 1000, 1000);
+    let meter = player.channel_meter(0).unwrap();
+    assert_eq!((meter.peak, meter.rms), (0.0, 0.0));
+
+    // Muted channels are not measured
+    player.set_mute_channel(0, true);
+    common::render(&mut player, 1000, 1000);
+    assert_eq!(player.channel_meter(0), Some(ChannelMeter::default()));
+
+    player.set_metering(false, 0);
+    assert_eq!(player.channel_meter(0), None);
+    assert_eq!(player.channel_scope(0).count(), 0);
+}
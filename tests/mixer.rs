@@ -0,0 +1,98 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+/// The same constant note on each of 3 channels
+fn dc_module() -> Module {
+    let mut module = common::empty_module(3, 16, 1);
+    module.instrument = vec![common::instrument(common::dc(), false)];
+    for slot in &mut module.pattern[0][0] {
+        slot.note = Note::try_from(49).unwrap();
+        slot.instrument = 1;
+    }
+    module
+}
+
+/// (left, right) output of each channel, one frame later
+fn levels(player: &mut XmrsPlayer) -> Vec<(f32, f32)> {
+    player.samples_from_channels().unwrap()
+}
+
+/// Output of each channel after a tick, once mixer settings are applied
+fn next_tick(player: &mut XmrsPlayer) -> Vec<(f32, f32)> {
+    common::render(player, TICK, TICK);
+    levels(player)
+}
+
+#[test]
+fn channel_volume_goes_through_the_ramp() {
+    let module = dc_module();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_volume_ramp(64);
+    let full = next_tick(&mut player)[0].0;
+    assert!(full > 0.01);
+
+    player.set_channel_volume(0, 0.5);
+    assert_eq!(player.get_channel_volume(0), Some(0.5));
+    common::render(&mut player, TICK - 1, TICK);
+    let mut previous = levels(&mut player)[0].0;
+    for _ in 0..100 {
+        let level = levels(&mut player)[0].0;
+        assert!(level <= previous);
+        assert!(previous - level <= full * 0.5 / 64.0 * 1.01);
+        previous = level;
+    }
+    assert!((previous - full * 0.5).abs() < 1e-5, "{previous} {full}");
+    assert_eq!(player.get_channel_volume(3), None);
+}
+
+#[test]
+fn solo_is_exclusive_or_additive() {
+    let module = dc_module();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    let playing = |levels: Vec<(f32, f32)>| -> Vec<bool> {
+        levels.iter().map(|&(left, _)| left > 0.0).collect()
+    };
+    assert_eq!(playing(next_tick(&mut player)), [true, true, true]);
+
+    player.solo_channel(1, true);
+    assert_eq!(playing(next_tick(&mut player)), [false, true, false]);
+    player.solo_channel(2, false);
+    assert_eq!(playing(next_tick(&mut player)), [false, true, true]);
+    assert!(player.is_channel_solo(1) && player.is_channel_solo(2));
+    player.solo_channel(0, true);
+    assert_eq!(playing(next_tick(&mut player)), [true, false, false]);
+    assert!(!player.is_channel_solo(1) && !player.is_channel_solo(2));
+
+    player.solo_channel(2, false);
+    player.unsolo_channel(0);
+    assert_eq!(playing(next_tick(&mut player)), [false, false, true]);
+    player.clear_solo();
+    assert_eq!(playing(next_tick(&mut player)), [true, true, true]);
+}
+
+#[test]
+fn mixer_settings_survive_goto() {
+    let module = dc_module();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_channel_volume(0, 0.5);
+    player.set_channel_pan_override(1, Some(0.0));
+    player.solo_channel(0, false);
+    player.solo_channel(1, false);
+    common::render(&mut player, 10 * TICK, TICK);
+
+    assert!(player.goto(0, 0, 0));
+    assert_eq!(player.get_channel_volume(0), Some(0.5));
+    assert_eq!(player.get_channel_pan_override(1), Some(0.0));
+    assert!(player.is_channel_solo(0) && player.is_channel_solo(1));
+    assert!(!player.is_channel_solo(2));
+
+    let levels = next_tick(&mut player);
+    assert!((levels[0].0 - levels[1].0 * 0.5_f32.sqrt() * 0.5).abs() < 1e-5);
+    assert_eq!(levels[1].1, 0.0);
+    assert_eq!(levels[2], (0.0, 0.0));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use xmrs::instr_vibrato::InstrVibrato;
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+/// Instrument 0 with a volume envelope, fadeout and auto-vibrato, on a silent song
+fn silent_module(channels: usize) -> Module {
+    let mut module = common::empty_module(channels, 64, 1);
+    let mut instrument = common::instrument(common::sample(2000, LoopType::Forward, false), true);
+    if let InstrumentType::Default(id) = &mut instrument.instr_type {
+        id.vibrato = InstrVibrato {
+            speed: 0.1,
+            depth: 0.5,
+            ..Default::default()
+        };
+    }
+    module.instrument = vec![instrument];
+    module
+}
+
+#[test]
+fn note_on_plays_a_live_instrument() {
+    let module = silent_module(1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, TICK, TICK);
+    let c4 = Note::try_from(49).unwrap();
+    assert!(player.note_on(0, 0, c4, 1.0));
+    assert_eq!(player.channel_info(0).unwrap().note, Some(c4));
+
+    // Envelope and auto-vibrato move each tick, between rows
+    let mut positions = vec![];
+    let mut pitches = vec![];
+    let mut volumes = vec![];
+    for _ in 0..4 {
+        common::render(&mut player, TICK, TICK);
+        let info = player.channel_info(0).unwrap();
+        positions.push(info.volume_envelope.unwrap());
+        pitches.push(info.pitch);
+        volumes.push(info.volume);
+    }
+    assert!(
+        positions.windows(2).all(|w| w[1] == w[0] + 1),
+        "{positions:?}"
+    );
+    assert!(volumes.windows(2).all(|w| w[1] > w[0]), "{volumes:?}");
+    assert!(pitches.windows(2).all(|w| w[1] != w[0]), "{pitches:?}");
+    assert!(pitches.iter().all(|p| (p - 48.0).abs() <= 0.5 + 1e-3));
+
+    // Released: the fadeout runs until the voice stops
+    player.note_off(0);
+    common::render(&mut player, TICK, TICK);
+    let info = player.channel_info(0).unwrap();
+    assert!(info.released);
+    assert!(info.fadeout < 1.0);
+    common::render(&mut player, 80 * TICK, TICK);
+    assert_eq!(player.channel_info(0).unwrap().volume, 0.0);
+
+    // Invalid instrument, channel or note
+    assert!(!player.note_on(0, 1, c4, 1.0));
+    assert!(!player.note_on(1, 0, c4, 1.0));
+    assert!(!player.note_on(0, 0, Note::KeyOff, 1.0));
+}
+
+#[test]
+fn free_channel_skips_busy_channels() {
+    let mut module = silent_module(3);
+    // Channel 0 is played by the song
+    module.pattern[0][0][0].note = Note::try_from(49).unwrap();
+    module.pattern[0][0][0].instrument = 1;
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, TICK, TICK);
+    let c5 = Note::try_from(61).unwrap();
+
+    assert_eq!(player.free_channel(), Some(1));
+    assert!(player.note_on(1, 0, c5, 1.0));
+    assert_eq!(player.free_channel(), Some(2));
+    assert!(player.note_on(2, 0, c5, 1.0));
+    assert_eq!(player.free_channel(), None);
+
+    // A released voice is busy until it has faded out
+    player.note_off(1);
+    common::render(&mut player, 4 * TICK, TICK);
+    assert_eq!(player.free_channel(), None);
+    common::render(&mut player, 80 * TICK, TICK);
+    assert_eq!(player.free_channel(), Some(1));
+}
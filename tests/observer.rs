@@ -0,0 +1,124 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Event {
+    On(usize, Note),
+    Off(usize),
+    Sync(usize, u8, u8),
+}
+
+#[derive(Default)]
+struct Recorder {
+    row: usize,
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl PlayerObserver for Recorder {
+    fn on_row(&mut self, _frame: u64, _order: usize, _pattern: usize, row: usize) {
+        self.row = row;
+    }
+
+    fn on_note_on(
+        &mut self,
+        _frame: u64,
+        _channel: usize,
+        _instrument: usize,
+        note: Note,
+        _volume: f32,
+    ) {
+        self.events.lock().unwrap().push(Event::On(self.row, note));
+    }
+
+    fn on_note_off(&mut self, _frame: u64, _channel: usize) {
+        self.events.lock().unwrap().push(Event::Off(self.row));
+    }
+
+    fn on_sync(&mut self, _frame: u64, _channel: usize, effect_type: u8, param: u8) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(Event::Sync(self.row, effect_type, param));
+    }
+}
+
+#[test]
+fn retriggers_and_cuts_are_reported() {
+    let c4 = Note::try_from(49).unwrap();
+    let mut module = common::empty_module(1, 8, 1);
+    let rows = &mut module.pattern[0];
+    let mut set =
+        |row: usize, note: Note, instrument: u8, effect_type: u8, effect_parameter: u8| {
+            let slot = &mut rows[row][0];
+            slot.note = note;
+            slot.instrument = instrument;
+            slot.effect_type = effect_type;
+            slot.effect_parameter = effect_parameter;
+        };
+    // E93: retrigger at tick 3
+    set(0, c4, 1, 0xE, 0x93);
+    // EC2: cut at tick 2
+    set(1, Note::None, 0, 0xE, 0xC2);
+    // R03: multi retrig note
+    set(2, c4, 1, 0x1B, 0x03);
+    // Key off next to an instrument
+    set(3, Note::KeyOff, 1, 0, 0);
+    // ED2: delayed note
+    set(4, c4, 1, 0xE, 0xD2);
+    set(5, Note::KeyOff, 0, 0, 0);
+
+    let recorder = Recorder::default();
+    let events = Arc::clone(&recorder.events);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_max_loop_count(1);
+    player.set_observer(Box::new(recorder));
+    common::render(&mut player, 8 * 6 * 882, 882);
+
+    use Event::*;
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            On(0, c4),
+            On(0, c4),
+            Off(1),
+            On(2, c4),
+            On(2, c4),
+            Off(3),
+            On(4, c4),
+            Off(5),
+        ]
+    );
+}
+
+#[test]
+fn sync_effect_can_filter_its_parameter() {
+    let mut module = common::empty_module(1, 4, 1);
+    for (row, param) in [0x20, 0x30, 0x20].into_iter().enumerate() {
+        module.pattern[0][row][0].effect_type = 0xC;
+        module.pattern[0][row][0].effect_parameter = param;
+    }
+
+    for (sync_effect, expected) in [
+        (Some((0xC, Some(0x20))), vec![(0, 0x20), (2, 0x20)]),
+        (Some((0xC, None)), vec![(0, 0x20), (1, 0x30), (2, 0x20)]),
+        (Some((0xA, None)), vec![]),
+        (None, vec![]),
+    ] {
+        let recorder = Recorder::default();
+        let events = Arc::clone(&recorder.events);
+        let mut player = XmrsPlayer::new(&module, 44100.0, false);
+        player.set_max_loop_count(1);
+        player.set_sync_effect(sync_effect);
+        player.set_observer(Box::new(recorder));
+        common::render(&mut player, 4 * 6 * 882, 882);
+
+        let expected: Vec<Event> = expected
+            .into_iter()
+            .map(|(row, param)| Event::Sync(row, 0xC, param))
+            .collect();
+        assert_eq!(*events.lock().unwrap(), expected, "{:?}", sync_effect);
+    }
+}
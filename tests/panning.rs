@@ -0,0 +1,154 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// (largest left, largest right) output
+fn sides(player: &mut XmrsPlayer) -> (f32, f32) {
+    let frames = common::render(player, 10_000, 1000);
+    let max = |side: usize| {
+        frames
+            .iter()
+            .skip(side)
+            .step_by(2)
+            .fold(0.0f32, |m, v| m.max(v.abs()))
+    };
+    (max(0), max(1))
+}
+
+fn assert_left(player: &mut XmrsPlayer, what: &str) {
+    let (left, right) = sides(player);
+    assert!(left > 1e-3 && right < 1e-6, "{}: {} {}", what, left, right);
+}
+
+/// A note on channel `channel`, with an optional effect
+fn one_note(channels: usize, channel: usize, effect: Option<(u8, u8)>) -> Module {
+    let mut module = common::empty_module(channels, 16, 1);
+    let slot = &mut module.pattern[0][0][channel];
+    slot.note = Note::try_from(49).unwrap();
+    slot.instrument = 1;
+    if let Some((effect_type, effect_parameter)) = effect {
+        slot.effect_type = effect_type;
+        slot.effect_parameter = effect_parameter;
+    }
+    module
+}
+
+#[test]
+fn panning_zero_is_left() {
+    // 800: song panning
+    let module = one_note(1, 0, Some((0x8, 0x00)));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_left(&mut player, "800");
+    assert_eq!(player.channel_info(0).unwrap().panning, 0.0);
+
+    // Mixer panning override
+    let module = one_note(1, 0, None);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_channel_pan_override(0, Some(0.0));
+    assert_left(&mut player, "pan override");
+    assert_eq!(player.channel_info(0).unwrap().panning, 0.0);
+
+    // Paula: channels 0 and 3 are on the left side
+    for channel in [0, 3] {
+        let module = one_note(4, channel, None);
+        let mut player = XmrsPlayer::new(&module, 44100.0, false);
+        player.set_paula_mode(Some(PaulaFilter::None));
+        assert_left(&mut player, "paula");
+        assert_eq!(player.channel_info(channel).unwrap().panning, 0.0);
+    }
+
+    // Sound effects over a silent song
+    let module = common::empty_module(1, 16, 1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_sfx_voices(1);
+    let c4 = Note::try_from(49).unwrap();
+    assert!(player.play_sfx(0, c4, 1.0, Some(0.0), 0).is_some());
+    assert_left(&mut player, "instrument sound effect");
+
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_sfx_voices(1);
+    let raw = player.add_sfx_sample(common::sample(1000, LoopType::Forward, false));
+    assert!(player.play_sfx_sample(raw, 8363.0, 1.0, 0.0, 0).is_some());
+    assert_left(&mut player, "raw sound effect");
+}
+
+/// Left and right output of a note on channel 0 panned by 8xx, at `separation`
+fn separated(param: u8, separation: f32) -> (Vec<f32>, Vec<f32>) {
+    let module = one_note(1, 0, Some((0x8, param)));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_stereo_separation(separation);
+    let frames = common::render(&mut player, 10_000, 1000);
+    let side = |side: usize| frames.iter().skip(side).step_by(2).copied().collect();
+    (side(0), side(1))
+}
+
+fn peak(side: &[f32]) -> f32 {
+    side.iter().fold(0.0f32, |m, v| m.max(v.abs()))
+}
+
+#[test]
+fn stereo_separation_narrows_and_widens() {
+    // 0%: mono
+    let (left, right) = separated(0x40, 0.0);
+    assert!(peak(&left) > 1e-3);
+    assert_eq!(left, right);
+
+    // 100%: the song panning, with the constant power panning law
+    let (left, right) = separated(0x40, 1.0);
+    let module = one_note(1, 0, Some((0x8, 0x40)));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    let authored = common::render(&mut player, 10_000, 1000);
+    let interleaved: Vec<f32> = left
+        .iter()
+        .zip(&right)
+        .flat_map(|(&l, &r)| [l, r])
+        .collect();
+    assert_eq!(interleaved, authored);
+    let panning = player.channel_info(0).unwrap().panning;
+    let ratio = peak(&right) / peak(&left);
+    let expected = (panning / (1.0 - panning)).sqrt();
+    assert!((ratio - expected).abs() < 1e-3, "{ratio} {expected}");
+
+    // 200%: wider, but a side never goes out of phase
+    let (wide_left, wide_right) = separated(0x40, 2.0);
+    assert!(peak(&wide_right) / peak(&wide_left) < ratio * 0.6);
+    assert!(peak(&wide_left) > peak(&left));
+    assert!(wide_left.iter().zip(&wide_right).all(|(l, r)| r * l >= 0.0));
+
+    // Hard left can't be wider
+    let (left, right) = separated(0x00, 1.0);
+    let (wide_left, wide_right) = separated(0x00, 2.0);
+    assert_eq!(left, wide_left);
+    assert!(peak(&right) < 1e-6 && peak(&wide_right) < 1e-6);
+
+    // Out of range settings are clamped
+    let module = one_note(1, 0, None);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_stereo_separation(3.0);
+    assert_eq!(player.get_stereo_separation(), 2.0);
+    player.set_stereo_separation(-1.0);
+    assert_eq!(player.get_stereo_separation(), 0.0);
+}
+
+#[test]
+fn mono_plays_hard_panned_channels_on_both_sides() {
+    // Paula: channel 0 is hard left
+    let module = one_note(4, 0, None);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_paula_mode(Some(PaulaFilter::None));
+    player.set_stereo_separation(2.0);
+    player.set_mono(true);
+    let frames = common::render(&mut player, 10_000, 1000);
+    assert!(peak(&frames) > 1e-3);
+    for frame in frames.chunks(2) {
+        assert_eq!(frame[0], frame[1]);
+    }
+
+    // The separation comes back with stereo, from the next tick
+    assert_eq!(player.get_stereo_separation(), 2.0);
+    player.set_mono(false);
+    common::render(&mut player, 882, 882);
+    assert_left(&mut player, "mono off");
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// Frames of a row at speed 6 and 125 BPM, at 44100 Hz
+const ROW: usize = 6 * 882;
+
+/// A note on channel 0 of a 4 channels Amiga song, of a sine of `cycles` per sample point
+fn one_note(note: u8, cycles: f32) -> Module {
+    let mut module = common::empty_module(4, 16, 1);
+    module.frequency_type = FrequencyType::AmigaFrequencies;
+    module.instrument = vec![common::instrument(common::sine(cycles), false)];
+    let slot = &mut module.pattern[0][0][0];
+    slot.note = Note::try_from(note).unwrap();
+    slot.instrument = 1;
+    module
+}
+
+#[test]
+fn led_filter_follows_e0x() {
+    let mut module = one_note(49, 0.01);
+    // E00 at row 2 switches the LED filter on, E01 at row 5 switches it off
+    module.pattern[0][2][1].effect_type = 0xE;
+    module.pattern[0][2][1].effect_parameter = 0x00;
+    module.pattern[0][5][2].effect_type = 0xE;
+    module.pattern[0][5][2].effect_parameter = 0x01;
+
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_paula_mode(Some(PaulaFilter::A500));
+    assert!(!player.is_led_filter_on());
+    // One frame into each row
+    common::render(&mut player, 1, 1);
+    for row in 0..8 {
+        assert_eq!(
+            player.is_led_filter_on(),
+            (2..5).contains(&row),
+            "row {row}"
+        );
+        common::render(&mut player, ROW, ROW);
+    }
+
+    // Without Paula emulation, there is no LED filter
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 3 * ROW, ROW);
+    assert!(!player.is_led_filter_on());
+}
+
+#[test]
+fn a500_attenuates_high_frequencies() {
+    let rendered = |module: &Module, filter: PaulaFilter| {
+        let mut player = XmrsPlayer::new(module, 44100.0, false);
+        player.set_paula_mode(Some(filter));
+        common::energy(&common::render(&mut player, 10_000, 1000))
+    };
+
+    // About 8 kHz, over the 4.4 kHz low-pass
+    let high = one_note(73, 0.25);
+    let unfiltered = rendered(&high, PaulaFilter::None);
+    let filtered = rendered(&high, PaulaFilter::A500);
+    assert!(unfiltered > 0.0);
+    assert!(filtered < unfiltered / 3.0, "{filtered} {unfiltered}");
+
+    // About 80 Hz goes through
+    let low = one_note(49, 0.01);
+    let unfiltered = rendered(&low, PaulaFilter::None);
+    let filtered = rendered(&low, PaulaFilter::A500);
+    assert!(
+        (filtered / unfiltered - 1.0).abs() < 0.05,
+        "{filtered} {unfiltered}"
+    );
+}
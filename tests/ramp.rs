@@ -0,0 +1,64 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// A constant sample: the output only moves with the channel volume
+fn dc_module() -> Module {
+    let c4 = Note::try_from(49).unwrap();
+    let mut module = common::empty_module(1, 8, 1);
+    module.instrument = vec![common::instrument(common::dc(), false)];
+    let rows = &mut module.pattern[0];
+    rows[0][0].note = c4;
+    rows[0][0].instrument = 1;
+    // EC3: cut at tick 3
+    rows[1][0].effect_type = 0xE;
+    rows[1][0].effect_parameter = 0xC3;
+    rows[2][0].note = c4;
+    rows[2][0].instrument = 1;
+    // E92: retrigger every 2 ticks
+    rows[3][0].effect_type = 0xE;
+    rows[3][0].effect_parameter = 0x92;
+    // Rxy: retrigger every 2 ticks, volume -8 then +8
+    rows[4][0].effect_type = 0x1B;
+    rows[4][0].effect_parameter = 0x42;
+    rows[5][0].effect_type = 0x1B;
+    rows[5][0].effect_parameter = 0xC2;
+    module
+}
+
+/// (largest output, largest change between two frames), retriggered voices fade out
+fn levels(ramp: usize) -> (f32, f32) {
+    let module = dc_module();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_volume_ramp(ramp);
+    player.set_note_fadeout(true);
+    player.set_max_loop_count(1);
+    let frames = common::render(&mut player, 8 * 6 * 882, 441);
+    let left: Vec<f32> = frames.iter().step_by(2).copied().collect();
+    let max = left.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+    let max_delta = left
+        .windows(2)
+        .fold(0.0f32, |m, w| m.max((w[1] - w[0]).abs()));
+    (max, max_delta)
+}
+
+#[test]
+fn cuts_and_retriggers_are_ramped() {
+    // Without ramp, the cut is a full step
+    let (max, max_delta) = levels(0);
+    assert!(max > 0.01);
+    assert!(max_delta >= max * 0.99);
+
+    for ramp in [32, 64, 256] {
+        let (max, max_delta) = levels(ramp);
+        let slope = max / ramp as f32;
+        assert!(
+            max_delta <= slope * 1.01 + 1e-6,
+            "ramp {}: delta {} slope {}",
+            ramp,
+            max_delta,
+            slope
+        );
+    }
+}
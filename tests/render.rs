@@ -0,0 +1,100 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+const FRAMES: usize = 60_000;
+
+/// Frames of the per-frame path, `samples_from_channels()` then `samples_apply_volume()`
+fn per_frame(player: &mut XmrsPlayer, frames: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(2 * frames);
+    for _ in 0..frames {
+        let (left, right) = match player.samples_from_channels() {
+            Some(samples) => player.samples_apply_volume(&samples),
+            None => (0.0, 0.0),
+        };
+        out.push(left);
+        out.push(right);
+    }
+    out
+}
+
+#[test]
+fn chunked_render_matches_per_frame_path() {
+    for (seed, amiga, historical) in [(1, false, false), (2, true, false), (3, false, true)] {
+        let module = common::busy_module(seed, amiga);
+        let reference = per_frame(&mut XmrsPlayer::new(&module, 44100.0, historical), FRAMES);
+        assert!(reference.iter().any(|&v| v != 0.0));
+
+        // 1 frame, chunks straddling tick boundaries (882 frames per tick at 125 BPM), one block
+        for chunk in [1, 7, 333, 882, 1000, 4096, FRAMES] {
+            let mut player = XmrsPlayer::new(&module, 44100.0, historical);
+            let rendered = common::render(&mut player, FRAMES, chunk);
+            assert!(rendered == reference, "seed {seed} chunk {chunk}");
+        }
+
+        let mut player = XmrsPlayer::new(&module, 44100.0, historical);
+        let (mut left, mut right) = (vec![0.0; FRAMES], vec![0.0; FRAMES]);
+        for (l, r) in left.chunks_mut(555).zip(right.chunks_mut(555)) {
+            player.render_planar(l, r);
+        }
+        let planar: Vec<f32> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(&l, &r)| [l, r])
+            .collect();
+        assert!(planar == reference, "seed {seed} planar");
+
+        let mut player = XmrsPlayer::new(&module, 44100.0, historical);
+        let iterated: Vec<f32> = (&mut player).take(2 * FRAMES).collect();
+        assert!(iterated == reference, "seed {seed} iterator");
+    }
+}
+
+#[test]
+fn chunked_render_matches_until_the_song_ends() {
+    let module = common::busy_module(4, false);
+    let mut reference_player = XmrsPlayer::new(&module, 22050.0, false);
+    reference_player.set_max_loop_count(1);
+    let reference = per_frame(&mut reference_player, 400_000);
+    let end = reference.iter().rposition(|&v| v != 0.0).unwrap();
+    assert!(end < 2 * 400_000 - 2);
+
+    let mut player = XmrsPlayer::new(&module, 22050.0, false);
+    player.set_max_loop_count(1);
+    let rendered = common::render(&mut player, 400_000, 3001);
+    assert!(rendered == reference);
+}
+
+/// A high note, read about 3 sample points per frame, of a looped sine of `cycles` per sample point
+fn high_note(cycles: f32, band_limit: Option<f32>) -> Vec<f32> {
+    let mut module = common::empty_module(1, 4, 1);
+    module.instrument = vec![common::instrument(common::sine(cycles), false)];
+    module.pattern[0][0][0].note = Note::try_from(85).unwrap();
+    module.pattern[0][0][0].instrument = 1;
+
+    let mut player = XmrsPlayer::new(&module, 22050.0, false);
+    player.set_band_limit(band_limit);
+    // Skip the attack
+    common::render(&mut player, 1_000, 1000);
+    common::render(&mut player, 5_000, 1000)
+}
+
+#[test]
+fn band_limit_removes_aliasing() {
+    let module = common::empty_module(1, 4, 1);
+    let player = XmrsPlayer::new(&module, 22050.0, false);
+    assert_eq!(player.get_band_limit(), Some(1.0));
+
+    // 0.4 cycles per point, read 3 points per frame: 1.2 cycles per frame, all above the output
+    // Nyquist frequency, is only heard folded back as aliasing
+    let aliased = common::energy(&high_note(0.4, None));
+    let filtered = common::energy(&high_note(0.4, Some(1.0)));
+    assert!(aliased > 0.0);
+    assert!(filtered < aliased / 100.0, "{filtered} {aliased}");
+
+    // 0.01 cycles per point, 0.03 cycles per frame, is kept
+    let plain = common::energy(&high_note(0.01, None));
+    let filtered = common::energy(&high_note(0.01, Some(1.0)));
+    assert!((filtered / plain - 1.0).abs() < 0.05, "{filtered} {plain}");
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+const ST3: Compatibility = Compatibility::ScreamTracker {
+    fast_volume_slides: false,
+};
+
+/// Two patterns of 4 rows loaded by `import_s3m`, with C-4 on row 0 and effects already converted
+/// to XM: `(pattern, row, effect)`
+fn s3m_module(effects: &[(usize, usize, (u8, u8))]) -> Module {
+    let mut module = common::empty_module(1, 4, 2);
+    module.comment = "XmRs reader".into();
+    module.instrument = vec![common::instrument(
+        common::sample(20000, LoopType::Forward, false),
+        false,
+    )];
+    module.pattern[0][0][0].note = Note::try_from(49).unwrap();
+    module.pattern[0][0][0].instrument = 1;
+    for &(pattern, row, effect) in effects {
+        let slot = &mut module.pattern[pattern][row][0];
+        (slot.effect_type, slot.effect_parameter) = effect;
+    }
+    module
+}
+
+/// `ChannelInfo` after each of the first `ticks` ticks
+fn ticks(module: &Module, compatibility: Compatibility, ticks: usize) -> Vec<ChannelInfo> {
+    let mut player = XmrsPlayer::new(module, 44100.0, false);
+    player.set_compatibility(compatibility);
+    (0..ticks)
+        .map(|_| {
+            common::render(&mut player, TICK, TICK);
+            player.channel_info(0).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn imported_modules_are_guessed_not_switched() {
+    let module = s3m_module(&[]);
+    assert_eq!(Compatibility::guess(&module), ST3);
+    let player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_eq!(player.get_compatibility(), Compatibility::Xm);
+}
+
+#[test]
+fn portamento_memory_is_shared_across_patterns() {
+    // F04 on the first row, E00 on the first row of the next pattern
+    let module = s3m_module(&[(0, 0, (0x1, 0x04)), (1, 0, (0x2, 0x00))]);
+    let steps = |compatibility| {
+        let pitches: Vec<f32> = ticks(&module, compatibility, 4 * 6 + 6)
+            .iter()
+            .map(|info| info.pitch)
+            .collect();
+        let steps = |from: usize| -> Vec<f32> {
+            pitches[from..from + 6]
+                .windows(2)
+                .map(|w| w[1] - w[0])
+                .collect()
+        };
+        (steps(0), steps(4 * 6))
+    };
+
+    let (up, down) = steps(ST3);
+    assert!(up.iter().all(|&s| s > 0.0), "{up:?}");
+    for (up, down) in up.iter().zip(&down) {
+        assert!((up + down).abs() < 1e-3, "{up} {down}");
+    }
+    // In XM, 2xx has its own memory
+    let (_, down) = steps(Compatibility::Xm);
+    assert!(down.iter().all(|&s| s == 0.0), "{down:?}");
+}
+
+#[test]
+fn fine_slides_are_recalled_as_fine_slides() {
+    // DF4 (fine volume slide down) on the first row, D00 on the first row of the next pattern
+    let module = s3m_module(&[(0, 0, (0xE, 0xB4)), (1, 0, (0xA, 0x00))]);
+    for (compatibility, slides) in [(ST3, true), (Compatibility::Xm, false)] {
+        let volumes: Vec<f32> = ticks(&module, compatibility, 4 * 6 + 6)
+            .iter()
+            .map(|info| info.volume)
+            .collect();
+        let before = volumes[4 * 6 - 1];
+        let row = &volumes[4 * 6..];
+        // On the first tick only
+        assert!(row.iter().all(|&v| v == row[0]), "{row:?}");
+        assert_eq!(
+            row[0] < before,
+            slides,
+            "{compatibility:?} {before} {row:?}"
+        );
+    }
+}
+
+#[test]
+fn qxy_retriggers_every_y_ticks() {
+    // Q23: retrigger every 3 ticks, volume down by 2 each time, on rows 0 to 2
+    let module = s3m_module(&[
+        (0, 0, (0x1B, 0x23)),
+        (0, 1, (0x1B, 0x23)),
+        (0, 2, (0x1B, 0x23)),
+    ]);
+    let infos = ticks(&module, ST3, 18);
+    let retriggers: Vec<usize> = (1..18)
+        .filter(|&t| infos[t].position < infos[t - 1].position)
+        .collect();
+    // The counter goes on across rows, on every tick but the first one of a row
+    assert_eq!(retriggers, [3, 7, 10, 14, 17]);
+    for pair in retriggers.windows(2) {
+        assert!(infos[pair[1]].volume < infos[pair[0]].volume);
+    }
+}
+
+#[test]
+fn ixy_tremor_counts_ticks_the_st3_way() {
+    // I21: on for 2 ticks, off for 1, over the first two rows
+    let module = s3m_module(&[(0, 0, (0x1D, 0x21)), (0, 1, (0x1D, 0x21))]);
+    let on = |compatibility| -> String {
+        ticks(&module, compatibility, 12)
+            .iter()
+            .map(|info| if info.volume > 0.0 { '1' } else { '0' })
+            .collect()
+    };
+    // Counted on the ticks after the first one of each row, where the volume is back on
+    assert_eq!(on(ST3), "111011101101");
+    // FT2 T21: on for 3 ticks, off for 2, and the first tick of a row keeps the state
+    assert_eq!(on(Compatibility::Xm), "111100011100");
+}
+
+#[test]
+fn fast_volume_slides_slide_on_the_first_tick() {
+    // D04 on rows 0 and 1
+    let module = s3m_module(&[(0, 0, (0xA, 0x04)), (0, 1, (0xA, 0x04))]);
+    let volumes = |fast_volume_slides| -> Vec<f32> {
+        let compatibility = Compatibility::ScreamTracker { fast_volume_slides };
+        ticks(&module, compatibility, 12)
+            .iter()
+            .map(|info| info.volume)
+            .collect()
+    };
+    let slow = volumes(false);
+    let fast = volumes(true);
+    // Row 1 starts lower, and the slide goes on the same way after
+    assert_eq!(slow[6], slow[5]);
+    assert!(fast[6] < fast[5]);
+    for t in 7..12 {
+        assert!(slow[t] < slow[t - 1] && fast[t] < fast[t - 1]);
+    }
+}
+
+#[test]
+fn vxx_scales_the_global_volume() {
+    // Vxx is imported as Gxx
+    let peak = |global_volume: u8, compatibility| {
+        let module = s3m_module(&[(0, 0, (0x10, global_volume))]);
+        let mut player = XmrsPlayer::new(&module, 44100.0, false);
+        player.set_compatibility(compatibility);
+        common::render(&mut player, 6 * TICK, TICK)[2 * TICK..]
+            .iter()
+            .fold(0.0f32, |peak, s| peak.max(s.abs()))
+    };
+    let peaks: Vec<f32> = [0x00, 0x10, 0x20, 0x40]
+        .iter()
+        .map(|&v| peak(v, ST3))
+        .collect();
+    assert_eq!(peaks[0], 0.0);
+    assert!(peaks.windows(2).all(|w| w[1] > w[0]), "{peaks:?}");
+    assert_eq!(peak(0x20, ST3), peak(0x20, Compatibility::Xm));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+#[test]
+fn seek_matches_playing() {
+    let module = common::busy_module(5, false);
+    for (ramp, fadeout, historical) in [(0, false, false), (64, true, false), (128, false, true)] {
+        let new_player = || {
+            let mut player = XmrsPlayer::new(&module, 44100.0, historical);
+            player.set_volume_ramp(ramp);
+            player.set_note_fadeout(fadeout);
+            player
+        };
+        let mut played = new_player();
+        let reference = common::render(&mut played, 120_000, 1024);
+
+        // Inside a tick, on a tick boundary, during a ramp, later in the song
+        for frame in [12_345, 882 * 20, 882 * 37 + 50, 100_000] {
+            let mut player = new_player();
+            assert!(player.seek_to_frame(frame as u64));
+            let mut check = new_player();
+            common::render(&mut check, frame, 1024);
+            assert_eq!(
+                player.get_current_table_index(),
+                check.get_current_table_index()
+            );
+            assert_eq!(player.get_current_row(), check.get_current_row());
+
+            let rendered = common::render(&mut player, 120_000 - frame, 1024);
+            assert!(
+                rendered[..] == reference[2 * frame..],
+                "ramp {ramp} frame {frame}"
+            );
+        }
+    }
+}
+
+#[test]
+fn seek_past_the_end_fails() {
+    let module = common::busy_module(5, false);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_max_loop_count(1);
+    assert!(!player.seek_to_frame(100_000_000));
+}
+
+/// A sample that ends long before the ramp to its volume does, then a new note
+fn short_note_module() -> Module {
+    let mut module = common::empty_module(1, 16, 1);
+    module.instrument = vec![common::instrument(
+        common::sample(300, LoopType::No, false),
+        false,
+    )];
+    let rows = &mut module.pattern[0];
+    rows[0][0].note = Note::try_from(49).unwrap();
+    rows[0][0].instrument = 1;
+    rows[8][0].note = Note::try_from(61).unwrap();
+    rows[8][0].instrument = 1;
+    module
+}
+
+fn short_note_player(module: &Module) -> XmrsPlayer<'_> {
+    let mut player = XmrsPlayer::new(module, 44100.0, false);
+    player.set_volume_ramp(20_000);
+    player.set_max_loop_count(1);
+    player
+}
+
+#[test]
+fn seek_matches_playing_across_a_note_end() {
+    let module = short_note_module();
+    let mut played = short_note_player(&module);
+    let reference = common::render(&mut played, 16 * 6 * 882, 882);
+
+    for frame in [6 * 882, 6 * 6 * 882, 10 * 6 * 882] {
+        let mut player = short_note_player(&module);
+        assert!(player.seek_to_frame(frame as u64));
+        let rendered = common::render(&mut player, 16 * 6 * 882 - frame, 882);
+        assert!(rendered[..] == reference[2 * frame..], "frame {frame}");
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn seek_leaves_the_state_of_playing_across_a_note_end() {
+    let module = short_note_module();
+    // The first note ended at row 2, its ramp runs until row 4
+    for frame in [3 * 6 * 882, 6 * 6 * 882] {
+        let mut played = short_note_player(&module);
+        common::render(&mut played, frame, 882);
+        let mut player = short_note_player(&module);
+        assert!(player.seek_to_frame(frame as u64));
+        let seeked = serde_json::to_string(&player.snapshot()).unwrap();
+        let played = serde_json::to_string(&played.snapshot()).unwrap();
+        assert!(seeked == played, "frame {frame}");
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+fn peak(frames: &[f32]) -> f32 {
+    frames.iter().fold(0.0f32, |m, v| m.max(v.abs()))
+}
+
+#[test]
+fn sfx_play_while_paused_and_after_the_end() {
+    let module = common::empty_module(1, 16, 1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_max_loop_count(1);
+    player.set_sfx_voices(1);
+    let raw = player.add_sfx_sample(common::dc());
+
+    player.pause(true);
+    assert!(player.play_sfx_sample(raw, 8363.0, 1.0, 0.5, 0).is_some());
+    assert!(peak(&common::render(&mut player, 2000, 500)) > 0.01);
+    player.pause(false);
+
+    // 16 rows at speed 6 and 125 BPM, then the song is over
+    let mut buffer = vec![0.0; 2 * 16 * 6 * 882];
+    player.render_interleaved(&mut buffer);
+    let mut buffer = vec![0.0; 2 * 2000];
+    assert_eq!(player.render_interleaved(&mut buffer), 0);
+    assert!(peak(&buffer) > 0.01);
+
+    // Instrument sound effects are ticked too: the note plays after the end
+    player.stop_all_sfx();
+    let c4 = Note::try_from(49).unwrap();
+    assert!(player.play_sfx(0, c4, 1.0, None, 0).is_some());
+    let mut buffer = vec![0.0; 2 * 2000];
+    player.render_interleaved(&mut buffer);
+    assert!(peak(&buffer) > 0.01);
+}
+
+#[test]
+fn samples_from_channels_include_sfx() {
+    let module = common::busy_module(5, false);
+    let start = |player: &mut XmrsPlayer| {
+        player.set_sfx_voices(2);
+        let raw = player.add_sfx_sample(common::sample(700, LoopType::Forward, false));
+        let c4 = Note::try_from(49).unwrap();
+        assert!(player.play_sfx(1, c4, 0.8, Some(0.2), 0).is_some());
+        assert!(player.play_sfx_sample(raw, 8000.0, 0.5, 0.7, 0).is_some());
+    };
+
+    let mut frames = XmrsPlayer::new(&module, 44100.0, false);
+    start(&mut frames);
+    let mut channels = XmrsPlayer::new(&module, 44100.0, false);
+    start(&mut channels);
+
+    for _ in 0..20_000 {
+        let samples = channels.samples_from_channels().unwrap();
+        assert_eq!(samples.len(), module.get_num_channels() + 2);
+        let expected = channels.samples_apply_volume(&samples);
+        assert_eq!(frames.sample(true), Some(expected));
+    }
+
+    // Paused: the music channels are silent, the sound effects go on
+    channels.pause(true);
+    let sfx: f32 = (0..2000)
+        .map(|_| {
+            let samples = channels.samples_from_channels().unwrap();
+            let (music, sfx) = samples.split_at(module.get_num_channels());
+            assert!(music.iter().all(|&s| s == (0.0, 0.0)));
+            sfx.iter().map(|s| s.0.abs() + s.1.abs()).sum::<f32>()
+        })
+        .sum();
+    assert!(sfx > 0.01);
+}
+
+#[test]
+fn raw_sfx_are_ramped_and_follow_separation() {
+    let module = common::empty_module(1, 16, 1);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_volume_ramp(64);
+    player.set_stereo_separation(0.0);
+    player.set_sfx_voices(1);
+    let raw = player.add_sfx_sample(common::dc());
+    assert!(player.play_sfx_sample(raw, 8363.0, 1.0, 0.0, 0).is_some());
+
+    let mut frames = common::render(&mut player, 2000, 500);
+    player.release_sfx(0);
+    frames.extend(common::render(&mut player, 2000, 500));
+
+    // Mono: a hard left panning reaches both sides
+    for frame in frames.chunks(2) {
+        assert_eq!(frame[0], frame[1]);
+    }
+    let left: Vec<f32> = frames.iter().step_by(2).copied().collect();
+    let max = peak(&left);
+    let max_delta = left
+        .windows(2)
+        .fold(left[0].abs(), |m, w| m.max((w[1] - w[0]).abs()));
+    assert!(max > 0.01);
+    assert!(max_delta <= max / 64.0 * 1.01, "{} {}", max, max_delta);
+    assert_eq!(*left.last().unwrap(), 0.0);
+}
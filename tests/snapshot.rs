@@ -0,0 +1,112 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+fn bits(frames: &[f32]) -> Vec<u32> {
+    frames.iter().map(|f| f.to_bits()).collect()
+}
+
+#[test]
+fn restore_replays_the_same_output() {
+    let mut module = common::busy_module(3, true);
+    // E00: LED filter on, before the snapshot
+    module.pattern[0][1][1].effect_type = 0xE;
+    module.pattern[0][1][1].effect_parameter = 0x00;
+
+    for paula in [None, Some(PaulaFilter::A500)] {
+        let mut player = XmrsPlayer::new(&module, 44100.0, false);
+        player.set_paula_mode(paula);
+        player.set_volume_ramp(64);
+        player.set_sfx_voices(2);
+        let raw = player.add_sfx_sample(common::sample(700, LoopType::Forward, false));
+        assert!(player
+            .play_sfx(1, Note::try_from(49).unwrap(), 0.8, Some(0.2), 1)
+            .is_some());
+
+        common::render(&mut player, 10_000, 512);
+        assert!(player.play_sfx_sample(raw, 8000.0, 0.5, 0.7, 1).is_some());
+        common::render(&mut player, 1_234, 512);
+
+        let state = player.snapshot();
+        let first = common::render(&mut player, 30_000, 700);
+        assert!(player.restore(&state).is_ok());
+        let second = common::render(&mut player, 30_000, 700);
+        assert_eq!(bits(&first), bits(&second), "paula {:?}", paula);
+    }
+}
+
+#[test]
+fn restore_rejects_other_players() {
+    let module = common::busy_module(3, false);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 20_000, 882);
+    let state = player.snapshot();
+
+    let mut other = XmrsPlayer::new(&module, 48000.0, false);
+    assert_eq!(other.restore(&state), Err(RestoreError::SampleRate));
+
+    let mut other = XmrsPlayer::new(&module, 44100.0, true);
+    assert_eq!(other.restore(&state), Err(RestoreError::Historical));
+
+    let mut other = XmrsPlayer::new(&module, 44100.0, false);
+    other.set_sfx_voices(1);
+    assert_eq!(other.restore(&state), Err(RestoreError::Sfx));
+
+    let fewer_channels = common::empty_module(2, 32, 3);
+    let mut other = XmrsPlayer::new(&fewer_channels, 44100.0, false);
+    assert_eq!(other.restore(&state), Err(RestoreError::Module));
+
+    let mut shorter_samples = common::busy_module(3, false);
+    shorter_samples.instrument = common::instruments()
+        .into_iter()
+        .map(|_| common::instrument(common::sample(100, LoopType::No, false), false))
+        .collect();
+    let mut other = XmrsPlayer::new(&shorter_samples, 44100.0, false);
+    assert!(matches!(
+        other.restore(&state),
+        Err(RestoreError::Channel(_))
+    ));
+}
+
+#[test]
+fn restore_rejects_rows_out_of_the_module() {
+    let module = common::empty_module(4, 32, 3);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 20 * 6 * 882, 882);
+    let state = player.snapshot();
+    assert!(state.get_current_row() >= 16);
+
+    let shorter_patterns = common::empty_module(4, 16, 3);
+    let mut other = XmrsPlayer::new(&shorter_patterns, 44100.0, false);
+    assert_eq!(other.restore(&state), Err(RestoreError::Position));
+    assert!(player.restore(&state).is_ok());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn saved_snapshot_replays_in_a_new_player() {
+    let module = common::busy_module(7, false);
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_volume_ramp(64);
+    common::render(&mut player, 25_000, 882);
+
+    let saved = serde_json::to_string(&player.snapshot()).unwrap();
+    let first = common::render(&mut player, 30_000, 700);
+
+    let state: PlayerState = serde_json::from_str(&saved).unwrap();
+    assert_eq!(state.get_format_version(), PlayerState::FORMAT_VERSION);
+    let mut fresh = XmrsPlayer::new(&module, 44100.0, false);
+    fresh.set_volume_ramp(64);
+    assert!(fresh.restore(&state).is_ok());
+    let second = common::render(&mut fresh, 30_000, 700);
+    assert_eq!(bits(&first), bits(&second));
+
+    // A snapshot saved with another format is refused
+    let version = format!("\"version\":{}", PlayerState::FORMAT_VERSION);
+    let other = saved.replacen(&version, "\"version\":0", 1);
+    assert_ne!(other, saved);
+    let state: PlayerState = serde_json::from_str(&other).unwrap();
+    assert_eq!(fresh.restore(&state), Err(RestoreError::Version));
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// 16 rows at speed 6, 125 BPM and 44100 Hz
+const PATTERN_FRAMES: u64 = 16 * 6 * 882;
+
+/// Two songs separated by a `+++` marker, the restart position points into the second one
+fn two_songs() -> Module {
+    let mut module = common::empty_module(1, 16, 3);
+    module.pattern_order = vec![0, 1, 254, 2];
+    module.restart_position = 3;
+    module
+}
+
+/// Table indices played during `patterns` patterns
+fn played_table_indices(player: &mut XmrsPlayer, patterns: usize) -> Vec<usize> {
+    let mut indices = vec![];
+    for _ in 0..patterns * 16 * 6 {
+        common::render(player, 882, 882);
+        let index = player.get_current_table_index();
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    indices
+}
+
+#[test]
+fn subsongs_stop_at_separators() {
+    let module = two_songs();
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    let subsongs = player.subsongs().to_vec();
+    assert_eq!(subsongs.len(), 2);
+    assert_eq!(subsongs[0].table_index, 0);
+    assert_eq!(subsongs[0].total_frames, 2 * PATTERN_FRAMES);
+    assert_eq!(subsongs[1].table_index, 3);
+    assert_eq!(subsongs[1].total_frames, PATTERN_FRAMES);
+
+    // Scanned once
+    let first = player.subsongs().as_ptr();
+    assert!(player.select_subsong(1));
+    assert!(core::ptr::eq(player.subsongs().as_ptr(), first));
+}
+
+#[test]
+fn subsongs_loop_on_themselves() {
+    let module = two_songs();
+
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    assert_eq!(played_table_indices(&mut player, 5), vec![0, 1]);
+
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    assert!(player.select_subsong(1));
+    assert_eq!(played_table_indices(&mut player, 5), vec![3]);
+
+    player.set_max_loop_count(1);
+    assert!(player.select_subsong(1));
+    common::render(&mut player, PATTERN_FRAMES as usize, 882);
+    assert!(common::render(&mut player, 882, 882)
+        .iter()
+        .all(|&s| s == 0.0));
+    assert_eq!(player.get_current_table_index(), 3);
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use xmrs::prelude::*;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: u64 = 882;
+
+#[derive(Default)]
+struct Played {
+    /// (frame, table index, row)
+    rows: Vec<(u64, usize, usize)>,
+    loops: Vec<u64>,
+}
+
+#[derive(Default)]
+struct Recorder(Arc<Mutex<Played>>);
+
+impl PlayerObserver for Recorder {
+    fn on_row(&mut self, frame: u64, order: usize, _pattern: usize, row: usize) {
+        self.0.lock().unwrap().rows.push((frame, order, row));
+    }
+
+    fn on_loop(&mut self, frame: u64, _loop_count: usize) {
+        self.0.lock().unwrap().loops.push(frame);
+    }
+}
+
+/// Rows, loops and length of the song really rendered
+fn play(module: &Module, sample_rate: f32, max_loop_count: usize) -> (Played, u64) {
+    let played = Arc::new(Mutex::new(Played::default()));
+    let mut player = XmrsPlayer::new(module, sample_rate, false);
+    player.set_max_loop_count(max_loop_count);
+    player.set_observer(Box::new(Recorder(played.clone())));
+    let mut total = 0;
+    let mut buffer = vec![0.0; 2 * 1000];
+    loop {
+        let frames = player.render_interleaved(&mut buffer);
+        total += frames as u64;
+        if frames < 1000 {
+            break;
+        }
+    }
+    let played = std::mem::take(&mut *played.lock().unwrap());
+    (played, total)
+}
+
+/// The timeline gives the length, the row start table and the loop point of the rendered song
+fn check(module: &Module, sample_rate: f32, max_loop_count: usize) -> SongTimeline {
+    let timeline = SongTimeline::new(module, sample_rate, false, max_loop_count);
+    let (played, total) = play(module, sample_rate, max_loop_count);
+    assert!(timeline.is_complete());
+    assert_eq!(timeline.total_frames(), total);
+
+    for (table_index, &pattern) in module.pattern_order.iter().enumerate() {
+        for row in 0..module.pattern[pattern].len() {
+            let first = played
+                .rows
+                .iter()
+                .find(|&&(_, ti, r)| ti == table_index && r == row)
+                .map(|&(frame, _, _)| frame);
+            assert_eq!(
+                timeline.row_start_frame(table_index, row),
+                first,
+                "table index {table_index} row {row}"
+            );
+        }
+    }
+
+    // Where the song first loops back, even if it ends there instead
+    match played.loops.first() {
+        Some(&frame) => {
+            let point = timeline.loop_point().unwrap();
+            assert_eq!(point.frame, frame);
+            match played.rows.iter().find(|r| r.0 == frame) {
+                Some(&(_, ti, row)) => assert_eq!((point.table_index, point.row), (ti, row)),
+                None => assert_eq!(frame, total),
+            }
+            assert_eq!(
+                Some(point.target_frame),
+                timeline.row_start_frame(point.table_index, point.row)
+            );
+        }
+        None => assert_eq!(timeline.loop_point(), None),
+    }
+    timeline
+}
+
+fn set_effect(module: &mut Module, pattern: usize, row: usize, effect_type: u8, param: u8) {
+    let slot = &mut module.pattern[pattern][row][0];
+    slot.effect_type = effect_type;
+    slot.effect_parameter = param;
+}
+
+#[test]
+fn plain_song() {
+    let module = common::empty_module(1, 16, 2);
+    let timeline = check(&module, 44100.0, 1);
+    assert_eq!(timeline.total_frames(), 2 * 16 * 6 * TICK);
+    assert_eq!(timeline.row_start_frame(1, 3), Some((16 + 3) * 6 * TICK));
+
+    let timeline = check(&module, 44100.0, 2);
+    assert_eq!(timeline.total_frames(), 2 * 2 * 16 * 6 * TICK);
+    let point = timeline.loop_point().unwrap();
+    assert_eq!((point.table_index, point.row), (0, 0));
+    assert_eq!(point.frame, 2 * 16 * 6 * TICK);
+    assert_eq!(point.target_frame, 0);
+
+    check(&module, 48000.0, 2);
+}
+
+#[test]
+fn speed_and_tempo_changes() {
+    let mut module = common::empty_module(1, 16, 1);
+    // F03: speed 3, F96: 150 BPM
+    set_effect(&mut module, 0, 2, 0xF, 0x03);
+    set_effect(&mut module, 0, 8, 0xF, 0x96);
+    let timeline = check(&module, 44100.0, 1);
+    assert_eq!(timeline.row_start_frame(0, 3), Some((2 * 6 + 3) * TICK));
+    assert_eq!(timeline.row_start_frame(0, 8), Some((2 * 6 + 6 * 3) * TICK));
+    check(&module, 48000.0, 2);
+}
+
+#[test]
+fn position_jump() {
+    let mut module = common::empty_module(1, 8, 3);
+    // B02 at row 3: order 1 is never played
+    set_effect(&mut module, 0, 3, 0xB, 0x02);
+    let timeline = check(&module, 44100.0, 1);
+    assert!(!timeline.is_reachable(1));
+    assert_eq!(timeline.row_start_frame(2, 0), Some(4 * 6 * TICK));
+    assert_eq!(timeline.total_frames(), (4 + 8) * 6 * TICK);
+
+    // B01 at the end of order 2: order 1 is played, then the song loops on order 2
+    set_effect(&mut module, 2, 7, 0xB, 0x01);
+    let timeline = check(&module, 44100.0, 2);
+    assert_eq!(timeline.row_start_frame(1, 0), Some((4 + 8) * 6 * TICK));
+    let point = timeline.loop_point().unwrap();
+    assert_eq!((point.table_index, point.row), (2, 0));
+    assert_eq!(point.frame, (4 + 8 + 8) * 6 * TICK);
+    check(&module, 48000.0, 3);
+}
+
+#[test]
+fn pattern_break() {
+    let mut module = common::empty_module(1, 8, 2);
+    // D04 at row 5: order 1 starts at row 4
+    set_effect(&mut module, 0, 5, 0xD, 0x04);
+    let timeline = check(&module, 44100.0, 1);
+    assert_eq!(timeline.row_start_frame(1, 4), Some(6 * 6 * TICK));
+    assert_eq!(timeline.row_start_frame(1, 0), None);
+    assert_eq!(timeline.total_frames(), (6 + 4) * 6 * TICK);
+    check(&module, 48000.0, 2);
+}
+
+#[test]
+fn pattern_loop() {
+    let mut module = common::empty_module(1, 8, 1);
+    // E60 at row 1, E62 at row 3: rows 1 to 3 are played three times
+    set_effect(&mut module, 0, 1, 0xE, 0x60);
+    set_effect(&mut module, 0, 3, 0xE, 0x62);
+    let timeline = check(&module, 44100.0, 1);
+    assert_eq!(timeline.row_start_frame(0, 4), Some((1 + 3 * 3) * 6 * TICK));
+    assert_eq!(timeline.total_frames(), (8 + 2 * 3) * 6 * TICK);
+    check(&module, 48000.0, 2);
+}
+
+#[test]
+fn pattern_delay() {
+    let mut module = common::empty_module(1, 8, 1);
+    // EE3 at row 2: the row lasts four times longer
+    set_effect(&mut module, 0, 2, 0xE, 0xE3);
+    let timeline = check(&module, 44100.0, 1);
+    assert_eq!(timeline.row_start_frame(0, 3), Some((2 + 4) * 6 * TICK));
+    assert_eq!(timeline.total_frames(), (8 + 3) * 6 * TICK);
+    check(&module, 48000.0, 2);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use xmrs::prelude::*;
+use xmrs::sample::LoopType;
+use xmrsplayer::prelude::*;
+
+/// Frames of a tick at speed 6 and 125 BPM, at 44100 Hz
+const TICK: usize = 882;
+
+/// A note with E43 (random vibrato) on row 0, E73 (random tremolo) on row 1, `effect` on rows 2 to 4
+fn lfo_module(effect: (u8, u8)) -> Module {
+    let mut module = common::empty_module(1, 16, 1);
+    module.instrument = vec![common::instrument(
+        common::sample(2000, LoopType::Forward, false),
+        false,
+    )];
+    let rows = &mut module.pattern[0];
+    rows[0][0].note = Note::try_from(49).unwrap();
+    rows[0][0].instrument = 1;
+    rows[0][0].effect_type = 0xE;
+    rows[0][0].effect_parameter = 0x43;
+    rows[1][0].effect_type = 0xE;
+    rows[1][0].effect_parameter = 0x73;
+    for row in &mut rows[2..5] {
+        (row[0].effect_type, row[0].effect_parameter) = effect;
+    }
+    module
+}
+
+fn rendered(module: &Module, seed: u32, historical: bool) -> Vec<f32> {
+    let mut player = XmrsPlayer::new(module, 44100.0, historical);
+    player.set_random_seed(seed);
+    common::render(&mut player, 6 * 6 * TICK, 1000)
+}
+
+#[test]
+fn random_waveforms_follow_the_seed() {
+    for effect in [(0x4, 0x8C), (0x7, 0x8C)] {
+        let module = lfo_module(effect);
+        assert_eq!(rendered(&module, 7, false), rendered(&module, 7, false));
+        assert!(rendered(&module, 7, false) != rendered(&module, 8, false));
+        // Square waveform in historical mode, the seed is not used
+        assert_eq!(rendered(&module, 7, true), rendered(&module, 8, true));
+    }
+
+    // Applied again when the song is rewound
+    let module = lfo_module((0x4, 0x8C));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    player.set_random_seed(7);
+    let first = common::render(&mut player, 6 * 6 * TICK, 1000);
+    assert!(player.seek_to_frame(0));
+    assert_eq!(common::render(&mut player, 6 * 6 * TICK, 1000), first);
+    assert_eq!(player.get_random_seed(), 7);
+}
+
+#[test]
+fn panbrello_moves_the_panning() {
+    // Y48: panbrello at speed 4, depth 8, on rows 2 to 4
+    let module = lfo_module((0x22, 0x48));
+    let mut player = XmrsPlayer::new(&module, 44100.0, false);
+    common::render(&mut player, 6 * TICK + 1, TICK);
+    let base = player.channel_info(0).unwrap().panning;
+    common::render(&mut player, 6 * TICK, TICK);
+    let panning: Vec<f32> = (0..17)
+        .map(|_| {
+            common::render(&mut player, TICK, TICK);
+            let info = player.channel_info(0).unwrap();
+            assert!(info.effects.panbrello);
+            info.panning
+        })
+        .collect();
+    assert!(panning.iter().all(|p| (0.0..=1.0).contains(p)));
+    assert!(panning.iter().any(|&p| p < base - 0.01), "{panning:?}");
+    assert!(panning.iter().any(|&p| p > base + 0.01), "{panning:?}");
+
+    // Back to the channel panning once the effect stops
+    common::render(&mut player, 6 * TICK, TICK);
+    let info = player.channel_info(0).unwrap();
+    assert!(!info.effects.panbrello);
+    assert_eq!(info.panning, base);
+}